@@ -1,9 +1,34 @@
+use std::io::IsTerminal;
+
 use clap::Parser;
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
 pub struct Args {
-    /// Input File
+    /// Files to open, `:bn` and `:bp` switch between them. `-` reads from stdin
     // #[arg(short, long)]
-    pub file_path: Option<String>,
+    pub file_paths: Vec<String>,
+
+    /// Syntax to highlight with regardless of the file extension, e.g. `rs`
+    #[arg(long)]
+    pub syntax: Option<String>,
+
+    /// Open the files for looking only, anything that would change or save them is refused
+    #[arg(short = 'R', long)]
+    pub readonly: bool,
+
+    /// Keymap file whose bindings go on top of the built-in ones, see `keymap/default.toml`
+    #[arg(long)]
+    pub keymap: Option<String>,
+}
+
+impl Args {
+    /// Whether the buffer should come from stdin, either asked for with `-` or piped in
+    pub fn reads_stdin(&self) -> bool {
+        match self.file_paths.first().map(String::as_str) {
+            Some("-") => true,
+            Some(_) => false,
+            None => !std::io::stdin().is_terminal(),
+        }
+    }
 }
@@ -0,0 +1,106 @@
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use ropey::Rope;
+
+use crate::editor::{Position, SCRATCH_BUFFER, text_colour::detect_syntax};
+
+/// State of one open file. The shown buffer's state lives in the [`Editor`](super::Editor)
+/// fields of the same name, its slot in `Editor::buffers` is a `Buffer::default()` placeholder
+/// that must never be read, it only gets filled when another buffer is shown
+#[derive(Default, Debug)]
+pub struct Buffer {
+    pub file_text: Rope,
+    pub file_path: String,
+    pub cursor: Position,
+    pub scroll: Position,
+    pub modified: bool,
+    pub detected_syntax: Option<String>,
+    pub marks: HashMap<char, Position>,
+    pub file_format: FileFormat,
+    pub no_final_newline: bool,
+}
+
+/// Line break a file is saved with, `\r\n` is read as `\n` and turned back on save
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    #[default]
+    Unix,
+    Dos,
+}
+
+impl FileFormat {
+    /// `dos` when most line breaks in `text` are `\r\n`
+    pub fn detect(text: &str) -> Self {
+        let breaks = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if crlf * 2 > breaks {
+            Self::Dos
+        } else {
+            Self::Unix
+        }
+    }
+
+    /// Name `:set fileformat` takes and the status line shows
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unix => "unix",
+            Self::Dos => "dos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(Self::Unix),
+            "dos" => Some(Self::Dos),
+            _ => None,
+        }
+    }
+
+    pub fn line_break(self) -> &'static str {
+        match self {
+            Self::Unix => "\n",
+            Self::Dos => "\r\n",
+        }
+    }
+}
+
+/// Splits the line ending convention off text read from a file: the text with `\n` line
+/// breaks, its format and whether the last line was missing its line break
+pub fn normalize_line_endings(text: String) -> (String, FileFormat, bool) {
+    let format = FileFormat::detect(&text);
+    let no_final_newline = !text.is_empty() && !text.ends_with('\n');
+    let text = if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    };
+    (text, format, no_final_newline)
+}
+
+impl Buffer {
+    /// Reads the file at `path`, a missing file is an empty buffer that gets created on save and
+    /// no path is a scratch buffer
+    pub fn open(path: Option<String>) -> Self {
+        let Some(path) = path else {
+            return Self {
+                file_path: SCRATCH_BUFFER.into(),
+                ..Default::default()
+            };
+        };
+        let (content, file_format, no_final_newline) =
+            normalize_line_endings(read_to_string(&path).unwrap_or_default());
+        let detected_syntax = Path::new(&path)
+            .extension()
+            .is_none()
+            .then(|| detect_syntax(&content))
+            .flatten();
+        Self {
+            file_text: content.into(),
+            file_path: path,
+            detected_syntax,
+            file_format,
+            no_final_newline,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::editor::settings::Settings;
+
+/// Defaults read from `config.toml` at startup
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Theme name as `:theme` takes it
+    pub theme: Option<String>,
+    /// Options as `:set` takes them, like `tabwidth = 2` or `number = true`
+    pub set: Settings,
+    /// Syntax to highlight a file extension with, like `h = "c"`
+    pub filetypes: HashMap<String, String>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/sexditor/config.toml`, or under `~/.config` when that isn't set
+    pub fn path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(dir.join("sexditor").join("config.toml"))
+    }
+
+    /// Reads the config at `path`, a missing file is the built-in defaults
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = match read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Writes the config to `path`, making the folders it goes in
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir().join(format!("sexditor_config_test_{}", std::process::id()));
+        let path = dir.join("nested").join("config.toml");
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.theme, None);
+        assert_eq!(config.set.tabwidth, Settings::default().tabwidth);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            "theme = \"solarized\"\n[set]\ntabwidth = 2\nnumber = true\n[filetypes]\nh = \"c\"\n",
+        )
+        .unwrap();
+        let mut config = Config::load(&path).unwrap();
+        assert_eq!(config.theme.as_deref(), Some("solarized"));
+        assert_eq!(config.set.tabwidth, 2);
+        assert!(config.set.number);
+        assert!(config.set.autoindent);
+        assert_eq!(config.filetypes["h"], "c");
+
+        config.set.expandtab = false;
+        config.save(&path).unwrap();
+        let saved = Config::load(&path).unwrap();
+        assert!(!saved.set.expandtab);
+        assert_eq!(saved.set.tabwidth, 2);
+        assert_eq!(saved.filetypes["h"], "c");
+
+        std::fs::write(&path, "[set]\ntabwdith = 2\n").unwrap();
+        assert!(Config::load(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
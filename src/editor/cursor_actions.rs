@@ -1,6 +1,65 @@
+use std::{borrow::Cow, ops::Range, sync::LazyLock};
+
 use fancy_regex::Regex;
+use ropey::str_utils::char_to_byte_idx;
+use unicode_segmentation::GraphemeCursor;
+use unicode_width::UnicodeWidthChar;
+
+use crate::editor::{CharFind, CursorDirection, Editor, Position, text_colour::SyntaxKind};
+
+/// Runs of the same unicode category, used by the word motions
+pub static WORD_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\p{Z}+|\p{P}+|\p{N}+|\p{L}+|\p{S}+)").unwrap());
+
+/// Terminal cells taken up by each char of `line`, wide characters take two and tabs reach
+/// to the next tab stop
+fn char_cells(line: &str, tabwidth: usize) -> impl Iterator<Item = Range<usize>> + '_ {
+    let tabwidth = tabwidth.max(1);
+    line.chars().scan(0, move |col, c| {
+        let start = *col;
+        *col += match c {
+            '\t' => tabwidth - start % tabwidth,
+            c => c.width().unwrap_or_default(),
+        };
+        Some(start..*col)
+    })
+}
+
+/// Byte offset of char `x` of `line` and `x`, both clamped to the end of the line
+fn byte_of_char(line: &str, x: usize) -> (usize, usize) {
+    match char_to_byte_idx(line, x) {
+        byte if byte < line.len() => (byte, x),
+        end => (end, x.min(line.chars().count())),
+    }
+}
+
+/// Char index of the grapheme cluster after the one at char `x` of `line`, or the end of the
+/// line. An accent or a ZWJ emoji sequence is one cluster made of several chars
+pub fn next_grapheme(line: &str, x: usize) -> usize {
+    let (byte, _) = byte_of_char(line, x);
+    match GraphemeCursor::new(byte, line.len(), true).next_boundary(line, 0) {
+        Ok(Some(next)) => x + line[byte..next].chars().count(),
+        _ => x,
+    }
+}
 
-use crate::editor::{CursorDirection, Editor, Position};
+/// Char index of the grapheme cluster before char `x` of `line`
+pub fn prev_grapheme(line: &str, x: usize) -> usize {
+    let (byte, x) = byte_of_char(line, x);
+    match GraphemeCursor::new(byte, line.len(), true).prev_boundary(line, 0) {
+        Ok(Some(prev)) => x - line[prev..byte].chars().count(),
+        _ => 0,
+    }
+}
+
+/// Moves char `x` of `line` back to the start of the grapheme cluster it's in, `x` at or past
+/// the end of the line stays
+pub fn grapheme_start(line: &str, x: usize) -> usize {
+    if x >= line.chars().count() {
+        return x;
+    }
+    prev_grapheme(line, x + 1)
+}
 
 pub trait CursorAction {
     fn cursor_at_end_of_file(&self) -> bool;
@@ -10,40 +69,96 @@ pub trait CursorAction {
     fn move_cursor(&mut self, dir: CursorDirection);
     fn move_to_end_of_pat(&mut self, pat: &Regex);
     fn move_to_start_of_pat(&mut self, pat: &Regex);
+    fn move_to_start_of_next_pat(&mut self, pat: &Regex);
+    fn move_to_start_of_prev_pat(&mut self, pat: &Regex);
+    fn find_char(&mut self, find: CharFind, repeat: bool) -> bool;
+    fn move_to_matching_bracket(&mut self) -> bool;
+    fn line_brackets(&self, y: usize) -> Vec<(usize, char)>;
+    fn move_to_end_of_line(&mut self);
+    fn move_to_first_non_blank(&mut self);
     fn move_to_next_line(&mut self);
     fn move_to_previous_line(&mut self);
-    fn line_at_cursor(&self) -> &str;
-    fn line_from_cursor(&self, y: i16) -> &str;
+    fn line_count(&self) -> usize;
+    fn line_at(&self, y: usize) -> Cow<'_, str>;
+    fn line_at_cursor(&self) -> Cow<'_, str>;
+    fn line_from_cursor(&self, y: isize) -> Cow<'_, str>;
+    fn display_column(&self) -> usize;
+    fn column_at(&self, pos: Position) -> usize;
+    fn chars_in_columns(&self, y: usize, cols: Range<usize>) -> Range<usize>;
+    fn position_from_byte_offset(&self, offset: usize) -> Position;
 }
 
 impl CursorAction for Editor {
     fn cursor_at_end_of_file(&self) -> bool {
-        self.cursor.y as usize > self.file_text.lines().count()
+        self.cursor.y >= self.line_count().saturating_sub(1)
     }
     fn cursor_at_start_of_file(&self) -> bool {
         self.cursor.y == 0
     }
-    fn line_at_cursor(&self) -> &str {
-        self.file_text
-            .lines()
-            .enumerate()
-            .find(|(idx, _)| *idx == self.cursor.y as usize)
-            .map(|(_, line)| line)
-            .unwrap_or_default()
-    }
-    fn line_from_cursor(&self, y: i16) -> &str {
-        self.file_text
-            .lines()
-            .enumerate()
-            .find(|(idx, _)| *idx == self.cursor.y as usize + y as usize)
-            .map(|(_, line)| line)
-            .unwrap_or_default()
+    /// Lines the way `str::lines` counts them, a trailing newline doesn't start another line
+    fn line_count(&self) -> usize {
+        let empty = self.file_text.len_chars() == 0;
+        self.file_text.len_lines() - usize::from(empty || self.ends_with_newline())
+    }
+    /// Line `y` without its line break, only copied if the rope splits it across chunks
+    fn line_at(&self, y: usize) -> Cow<'_, str> {
+        if y >= self.line_count() {
+            return Cow::Borrowed("");
+        }
+        let strip = |line: &str| -> usize {
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            line.strip_suffix('\r').unwrap_or(line).len()
+        };
+        match Cow::from(self.file_text.line(y)) {
+            Cow::Borrowed(line) => Cow::Borrowed(&line[..strip(line)]),
+            Cow::Owned(mut line) => {
+                line.truncate(strip(&line));
+                Cow::Owned(line)
+            }
+        }
+    }
+    fn line_at_cursor(&self) -> Cow<'_, str> {
+        self.line_at(self.cursor.y)
+    }
+    fn line_from_cursor(&self, y: isize) -> Cow<'_, str> {
+        self.line_at(self.cursor.y.wrapping_add_signed(y))
+    }
+    /// Terminal cells taken up by the line before the cursor, wide characters count twice
+    /// and tabs reach to the next tab stop
+    fn display_column(&self) -> usize {
+        self.column_at(self.cursor)
+    }
+    fn column_at(&self, pos: Position) -> usize {
+        char_cells(&self.line_at(pos.y), self.settings.tabwidth)
+            .take(pos.x)
+            .last()
+            .map_or(0, |cells| cells.end)
+    }
+    /// Chars of line `y` that share at least one cell with `cols`, a tab or wide character
+    /// sticking out of either edge is taken whole
+    fn chars_in_columns(&self, y: usize, cols: Range<usize>) -> Range<usize> {
+        let cells: Vec<_> = char_cells(&self.line_at(y), self.settings.tabwidth).collect();
+        let start = cells.iter().position(|c| c.end > cols.start);
+        let end = cells.iter().position(|c| c.start >= cols.end);
+        let end = end.unwrap_or(cells.len());
+        start.unwrap_or(end).min(end)..end
+    }
+    /// Inverse of `get_byte_offset`, offsets past the end land at the end of the text
+    fn position_from_byte_offset(&self, offset: usize) -> Position {
+        let idx = self
+            .file_text
+            .byte_to_char(offset.min(self.file_text.len_bytes()));
+        let y = self.file_text.char_to_line(idx);
+        Position {
+            x: idx - self.file_text.line_to_char(y),
+            y,
+        }
     }
     fn cursor_at_start_of_line(&self) -> bool {
         self.cursor.x == 0
     }
     fn cursor_at_end_of_line(&self) -> bool {
-        self.cursor.x as usize >= self.line_at_cursor().chars().count()
+        self.cursor.x >= self.line_at_cursor().chars().count()
     }
     fn move_cursor(&mut self, dir: CursorDirection) {
         match dir {
@@ -52,22 +167,16 @@ impl CursorAction for Editor {
                     return;
                 }
                 self.cursor.y -= 1;
-                let new_line_char_count =
-                    u16::try_from(self.line_at_cursor().chars().count()).unwrap_or_default();
-                if self.cursor.x > new_line_char_count {
-                    self.cursor.x = new_line_char_count;
-                }
+                let line = self.line_at_cursor();
+                self.cursor.x = grapheme_start(&line, self.cursor.x.min(line.chars().count()));
             }
             CursorDirection::Down => {
                 if self.cursor_at_end_of_file() {
                     return;
                 }
                 self.cursor.y += 1;
-                let new_line_char_count =
-                    u16::try_from(self.line_at_cursor().chars().count()).unwrap_or_default();
-                if self.cursor.x > new_line_char_count {
-                    self.cursor.x = new_line_char_count;
-                }
+                let line = self.line_at_cursor();
+                self.cursor.x = grapheme_start(&line, self.cursor.x.min(line.chars().count()));
             }
             CursorDirection::Left => {
                 if self.cursor_at_start_of_file() && self.cursor_at_start_of_line() {
@@ -76,14 +185,14 @@ impl CursorAction for Editor {
                 if self.cursor_at_start_of_line() {
                     self.move_to_previous_line();
                 } else {
-                    self.cursor.x -= 1;
+                    self.cursor.x = prev_grapheme(&self.line_at_cursor(), self.cursor.x);
                 }
             }
             CursorDirection::Right => {
                 if self.cursor_at_end_of_line() {
                     self.move_to_next_line();
                 } else {
-                    self.cursor.x += 1;
+                    self.cursor.x = next_grapheme(&self.line_at_cursor(), self.cursor.x);
                 }
             }
         }
@@ -94,7 +203,7 @@ impl CursorAction for Editor {
 
         let start_byte = line
             .char_indices()
-            .nth(self.cursor.x as usize)
+            .nth(self.cursor.x)
             .map_or(line.len(), |(i, _)| i);
 
         let slice = &line[start_byte..];
@@ -105,30 +214,205 @@ impl CursorAction for Editor {
 
         let extra_chars = slice[..mat.end()].chars().count();
 
-        self.cursor.x += u16::try_from(extra_chars).unwrap_or_default();
+        self.cursor.x += extra_chars;
     }
     fn move_to_start_of_pat(&mut self, pat: &Regex) {
         let line = self.line_at_cursor();
 
         let cursor_byte = line
             .char_indices()
-            .nth(self.cursor.x as usize)
+            .nth(self.cursor.x)
             .map_or(line.len(), |(i, _)| i);
 
         let before = &line[..cursor_byte];
 
-        let reversed: String = before.chars().rev().collect();
+        // The last match before the cursor is the token it ends in, matching forwards keeps
+        // graphemes and patterns that aren't symmetric intact
+        let Some(mat) = pat.find_iter(before).filter_map(Result::ok).last() else {
+            return;
+        };
+
+        self.cursor.x = before[..mat.start()].chars().count();
+    }
 
-        let Ok(Some(mat)) = pat.find(&reversed) else {
+    /// Skips the token under the cursor and any whitespace after it, continuing on the
+    /// following lines. Empty lines count as a word like they do in vim
+    fn move_to_start_of_next_pat(&mut self, pat: &Regex) {
+        let line = self.line_at_cursor();
+        let chars: Vec<char> = line.chars().collect();
+        let mut x = self.cursor.x;
+
+        if chars.get(x).is_some_and(|c| !c.is_whitespace()) {
+            let byte = line.char_indices().nth(x).map_or(line.len(), |(i, _)| i);
+            x += match pat.find(&line[byte..]) {
+                Ok(Some(mat)) if mat.start() == 0 => line[byte..byte + mat.end()].chars().count(),
+                _ => 1,
+            };
+        }
+        while chars.get(x).is_some_and(|c| c.is_whitespace()) {
+            x += 1;
+        }
+        if x < chars.len() {
+            self.cursor.x = x;
+            return;
+        }
+
+        for y in self.cursor.y.saturating_add(1)..self.line_count() {
+            let next = self.line_at(y);
+            if next.is_empty() {
+                self.cursor = Position { x: 0, y };
+                return;
+            }
+            if let Some(x) = next.chars().position(|c| !c.is_whitespace()) {
+                self.cursor = Position { x, y };
+                return;
+            }
+        }
+        // no word left in the file, stop on the last character like vim does
+        self.cursor.x = chars.len().saturating_sub(1);
+    }
+    /// Moves to the start of the previous token, skipping whitespace and continuing on the
+    /// lines above when there is nothing but whitespace before the cursor
+    fn move_to_start_of_prev_pat(&mut self, pat: &Regex) {
+        let before: Vec<char> = self.line_at_cursor().chars().take(self.cursor.x).collect();
+        let trailing_ws = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        if trailing_ws < before.len() {
+            self.cursor.x -= trailing_ws;
+            self.move_to_start_of_pat(pat);
             return;
+        }
+
+        for y in (0..self.cursor.y).rev() {
+            let prev = self.line_at(y);
+            if prev.is_empty() {
+                self.cursor = Position { x: 0, y };
+                return;
+            }
+            let trailing_ws = prev.chars().rev().take_while(|c| c.is_whitespace()).count();
+            let len = prev.chars().count();
+            if trailing_ws < len {
+                self.cursor = Position {
+                    x: len - trailing_ws,
+                    y,
+                };
+                self.move_to_start_of_pat(pat);
+                return;
+            }
+        }
+        self.cursor.x = 0;
+    }
+
+    /// Moves to the next (or previous) `find.target` on the current line, returns whether
+    /// it was found. Repeating a till motion skips the target right next to the cursor
+    fn find_char(&mut self, find: CharFind, repeat: bool) -> bool {
+        let chars: Vec<char> = self.line_at_cursor().chars().collect();
+        let x = self.cursor.x.min(chars.len());
+        let skip = usize::from(find.till && repeat);
+        let found = if find.forward {
+            (x + 1 + skip..chars.len())
+                .find(|idx| chars[*idx] == find.target)
+                .map(|idx| if find.till { idx - 1 } else { idx })
+        } else {
+            (0..x.saturating_sub(skip))
+                .rev()
+                .find(|idx| chars[*idx] == find.target)
+                .map(|idx| if find.till { idx + 1 } else { idx })
+        };
+        let Some(found) = found else {
+            return false;
         };
+        self.cursor.x = found;
+        true
+    }
 
-        let matched_chars = reversed[..mat.end()].chars().count();
+    /// Puts the cursor on the last character of the line, not past it
+    fn move_to_end_of_line(&mut self) {
+        let line = self.line_at_cursor();
+        self.cursor.x = prev_grapheme(&line, line.chars().count());
+    }
+    /// Puts the cursor on the first non whitespace character, or the last character
+    /// of a line that is all whitespace
+    fn move_to_first_non_blank(&mut self) {
+        let line = self.line_at_cursor();
+        let x = line
+            .chars()
+            .position(|c| !c.is_whitespace())
+            .unwrap_or_else(|| line.chars().count().saturating_sub(1));
+        self.cursor.x = x;
+    }
+    /// Jumps from the first bracket at or after the cursor on this line to its partner,
+    /// tracking nesting across lines. Brackets the syntax sees as part of a literal or
+    /// comment are skipped. Returns false if there is no partner
+    fn move_to_matching_bracket(&mut self) -> bool {
+        let y = self.cursor.y;
+        let line = self.line_brackets(y);
+        let Some(start) = line.iter().position(|(x, _)| *x >= self.cursor.x) else {
+            return false;
+        };
+        let bracket = line[start].1;
+        let (open, close, forward) = match bracket {
+            '(' | ')' => ('(', ')', bracket == '('),
+            '[' | ']' => ('[', ']', bracket == '['),
+            _ => ('{', '}', bracket == '{'),
+        };
 
-        self.cursor.x = self
-            .cursor
-            .x
-            .saturating_sub(matched_chars.try_into().unwrap_or_default());
+        // only parses lines until the partner shows up, not the whole file
+        let mut depth = 0usize;
+        let mut step = |c: char| {
+            if c == bracket {
+                depth += 1;
+            } else if c == open || c == close {
+                depth -= 1;
+            }
+            depth == 0
+        };
+        let found = if forward {
+            let rest = line[start..].iter().map(|&(x, c)| (x, y, c));
+            let below = (y + 1..self.line_count()).flat_map(|y| {
+                self.line_brackets(y)
+                    .into_iter()
+                    .map(move |(x, c)| (x, y, c))
+            });
+            rest.chain(below).find(|(_, _, c)| step(*c))
+        } else {
+            let rest = line[..=start].iter().rev().map(|&(x, c)| (x, y, c));
+            let above = (0..y).rev().flat_map(|y| {
+                self.line_brackets(y)
+                    .into_iter()
+                    .rev()
+                    .map(move |(x, c)| (x, y, c))
+            });
+            rest.chain(above).find(|(_, _, c)| step(*c))
+        };
+        let Some((x, y, _)) = found else {
+            return false;
+        };
+        self.cursor = Position { x, y };
+        true
+    }
+    /// Char index and bracket of every bracket on line `y` that isn't inside a literal or
+    /// comment
+    fn line_brackets(&self, y: usize) -> Vec<(usize, char)> {
+        let line = self.line_at(y);
+        let mut brackets = Vec::new();
+        let mut x = 0;
+        for (token, kind) in self.syntax.parse(&line) {
+            if !matches!(kind, SyntaxKind::Literal | SyntaxKind::Comment) {
+                brackets.extend(
+                    token
+                        .chars()
+                        .enumerate()
+                        .filter(|(_, c)| "()[]{}".contains(*c))
+                        .map(|(idx, c)| (x + idx, c)),
+                );
+            }
+            x += token.chars().count();
+        }
+        brackets
     }
 
     fn move_to_next_line(&mut self) {
@@ -140,8 +424,250 @@ impl CursorAction for Editor {
 
     fn move_to_previous_line(&mut self) {
         self.cursor = Position {
-            x: u16::try_from(self.line_from_cursor(-1).chars().count()).unwrap_or_default(),
+            x: self.line_from_cursor(-1).chars().count(),
             y: self.cursor.y - 1,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::text_actions::TextAction;
+
+    fn editor(text: &str) -> Editor {
+        Editor {
+            file_text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_move_down_stops_on_last_line() {
+        for text in ["a\nb\nc", "a\nb\nc\n"] {
+            let mut editor = editor(text);
+            for _ in 0..5 {
+                editor.move_cursor(CursorDirection::Down);
+            }
+            assert_eq!(editor.cursor.y, 2, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_move_by_grapheme() {
+        // an accent, a flag and a ZWJ family are each one step
+        let mut editor = editor("ae\u{301}🇩🇪👨\u{200d}👩\u{200d}👧b\nx\u{301}yz");
+        let starts = [1, 3, 5, 10, 11];
+        for x in starts {
+            editor.move_cursor(CursorDirection::Right);
+            assert_eq!(editor.cursor.x, x);
+        }
+        for x in starts.iter().rev().skip(1).chain([&0]) {
+            editor.move_cursor(CursorDirection::Left);
+            assert_eq!(editor.cursor.x, *x);
+        }
+        // up and down land on the start of the cluster under the column
+        editor.cursor = Position { x: 2, y: 1 };
+        editor.move_cursor(CursorDirection::Up);
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+        editor.move_cursor(CursorDirection::Down);
+        assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+        editor.move_to_end_of_line();
+        assert_eq!(editor.cursor.x, 3);
+        editor.cursor.y = 0;
+        editor.move_to_end_of_line();
+        assert_eq!(editor.cursor.x, 10);
+    }
+
+    #[test]
+    fn test_word_motion_crosses_lines() {
+        let mut editor = editor("foo bar  \n\n  baz.qux\n");
+        let starts = [(4, 0), (0, 1), (2, 2), (5, 2), (6, 2)];
+        for (x, y) in starts {
+            editor.move_to_start_of_next_pat(&WORD_PATTERN);
+            assert_eq!(editor.cursor, Position { x, y });
+        }
+        for (x, y) in starts.iter().rev().skip(1).chain([&(0, 0)]) {
+            editor.move_to_start_of_prev_pat(&WORD_PATTERN);
+            assert_eq!(editor.cursor, Position { x: *x, y: *y });
+        }
+    }
+
+    #[test]
+    fn test_word_motion_punctuation_runs() {
+        let mut editor = editor("foo::bar(+=)");
+        for x in [3, 5, 8] {
+            editor.move_to_start_of_next_pat(&WORD_PATTERN);
+            assert_eq!(editor.cursor.x, x);
+        }
+    }
+
+    #[test]
+    fn test_word_motion_multiple_spaces() {
+        let mut editor = editor("a    b\t\t c");
+        editor.move_to_start_of_next_pat(&WORD_PATTERN);
+        assert_eq!(editor.cursor.x, 5);
+        editor.move_to_start_of_next_pat(&WORD_PATTERN);
+        assert_eq!(editor.cursor.x, 9);
+    }
+
+    #[test]
+    fn test_word_motion_unicode() {
+        let mut editor = editor("naïve café 😀😀 über");
+        let starts = [6, 11, 14];
+        for x in starts {
+            editor.move_to_start_of_next_pat(&WORD_PATTERN);
+            assert_eq!(editor.cursor.x, x);
+        }
+        for x in starts.iter().rev().skip(1).chain([&0]) {
+            editor.move_to_start_of_prev_pat(&WORD_PATTERN);
+            assert_eq!(editor.cursor.x, *x);
+        }
+    }
+
+    #[test]
+    fn test_word_motion_end_of_file() {
+        let mut editor = editor("one\ntwo  \n   \n");
+        editor.cursor = Position { x: 0, y: 1 };
+        editor.move_to_start_of_next_pat(&WORD_PATTERN);
+        assert_eq!(editor.cursor, Position { x: 4, y: 1 });
+        editor.move_to_start_of_next_pat(&WORD_PATTERN);
+        assert_eq!(editor.cursor, Position { x: 4, y: 1 });
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let mut editor = editor("fn a(b: [u8]) {\n    if (x) { y }\n}\n");
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 12, y: 0 });
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 4, y: 0 });
+        editor.cursor.x = 13;
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 0, y: 2 });
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 14, y: 0 });
+
+        let mut editor = self::editor("a\n漢(字\n)");
+        editor.cursor.y = 1;
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 0, y: 2 });
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn test_matching_bracket_skips_literals_and_comments() {
+        let mut editor = editor("f(\")\", // )\n  \"(\"\n)");
+        editor.file_path = "main.rs".into();
+        editor.reload_syntax();
+        editor.cursor.x = 1;
+        assert!(editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 0, y: 2 });
+
+        let mut literal_first = self::editor("\"(\" (a)");
+        literal_first.file_path = "main.rs".into();
+        literal_first.reload_syntax();
+        assert!(literal_first.move_to_matching_bracket());
+        assert_eq!(literal_first.cursor, Position { x: 6, y: 0 });
+    }
+
+    #[test]
+    fn test_unmatched_bracket() {
+        let mut editor = editor("a ( b\nno brackets");
+        assert!(!editor.move_to_matching_bracket());
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+        editor.cursor.y = 1;
+        assert!(!editor.move_to_matching_bracket());
+    }
+
+    #[test]
+    fn test_line_motions() {
+        let mut editor = editor("\t  äöü\tx\n\n \t \n");
+        editor.move_to_end_of_line();
+        assert_eq!(editor.cursor.x, 7);
+        editor.move_to_first_non_blank();
+        assert_eq!(editor.cursor.x, 3);
+
+        editor.cursor = Position { x: 0, y: 1 };
+        editor.move_to_end_of_line();
+        assert_eq!(editor.cursor.x, 0);
+        editor.move_to_first_non_blank();
+        assert_eq!(editor.cursor.x, 0);
+
+        editor.cursor = Position { x: 0, y: 2 };
+        editor.move_to_first_non_blank();
+        assert_eq!(editor.cursor.x, 2);
+    }
+
+    #[test]
+    fn test_display_column_expands_tabs() {
+        let mut editor = editor("a\tb\t\t好c");
+        let columns: Vec<usize> = (0..=7)
+            .map(|x| {
+                editor.cursor.x = x;
+                editor.display_column()
+            })
+            .collect();
+        assert_eq!(columns, [0, 1, 4, 5, 8, 12, 14, 15]);
+
+        editor.settings.tabwidth = 2;
+        editor.cursor.x = 2;
+        assert_eq!(editor.display_column(), 2);
+    }
+
+    #[test]
+    fn test_find_char() {
+        let mut editor = editor("ä,ö,ü");
+        let find = |forward, till| CharFind {
+            forward,
+            till,
+            target: ',',
+        };
+        assert!(editor.find_char(find(true, false), false));
+        assert_eq!(editor.cursor.x, 1);
+        assert!(editor.find_char(find(true, true), true));
+        assert_eq!(editor.cursor.x, 2);
+        assert!(editor.find_char(find(false, false), false));
+        assert_eq!(editor.cursor.x, 1);
+        assert!(!editor.find_char(find(false, true), false));
+        assert_eq!(editor.cursor.x, 1);
+
+        editor.cursor.x = 4;
+        assert!(editor.find_char(find(false, true), false));
+        assert_eq!(editor.cursor.x, 4);
+        assert!(editor.find_char(find(false, true), true));
+        assert_eq!(editor.cursor.x, 2);
+        editor.cursor.x = 3;
+        assert!(!editor.find_char(find(true, false), false));
+    }
+
+    #[test]
+    fn test_position_from_byte_offset() {
+        let editor = editor("ab\nc\n");
+        assert_eq!(editor.position_from_byte_offset(0), Position { x: 0, y: 0 });
+        assert_eq!(editor.position_from_byte_offset(2), Position { x: 2, y: 0 });
+        assert_eq!(editor.position_from_byte_offset(3), Position { x: 0, y: 1 });
+        assert_eq!(editor.position_from_byte_offset(5), Position { x: 0, y: 2 });
+        assert_eq!(
+            editor.position_from_byte_offset(99),
+            Position { x: 0, y: 2 }
+        );
+    }
+
+    #[test]
+    fn test_position_from_byte_offset_multibyte() {
+        let editor = editor("äö\nüx");
+        assert_eq!(editor.position_from_byte_offset(4), Position { x: 2, y: 0 });
+        assert_eq!(editor.position_from_byte_offset(7), Position { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn test_position_round_trips_byte_offset() {
+        let editor = editor("fn ä() {\n    ü\n}");
+        for (offset, _) in editor.file_text.to_string().char_indices() {
+            let pos = editor.position_from_byte_offset(offset);
+            assert_eq!(editor.get_byte_offset(pos), offset);
+        }
+    }
+}
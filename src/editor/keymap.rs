@@ -0,0 +1,335 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{Context, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::editor::EditorMode;
+
+/// A command a key can be bound to in a keymap file, named in snake case there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstNonBlank,
+    MatchingBracket,
+    GotoLine,
+    /// `gg`, the first line or the line of the count
+    GotoFirstLine,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    HalfPageDown,
+    HalfPageUp,
+    SearchNext,
+    SearchPrevious,
+    RepeatFind,
+    RepeatFindReverse,
+    Insert,
+    Append,
+    AppendToLine,
+    InsertAtFirstNonBlank,
+    OpenBelow,
+    OpenAbove,
+    ReplaceMode,
+    Visual,
+    VisualBlock,
+    Command,
+    Search,
+    /// `g Ctrl-g`, the same counts as `:stats`
+    FileStats,
+    NormalMode,
+    DeleteChar,
+    DeleteCharBefore,
+    SubstituteChar,
+    DeleteToLineEnd,
+    ChangeToLineEnd,
+    PasteAfter,
+    PasteBefore,
+    JoinLines,
+    /// Adds an empty line below the cursor line without leaving normal mode
+    BlankLineBelow,
+    /// Adds an empty line above the cursor line without leaving normal mode
+    BlankLineAbove,
+    ShiftRight,
+    ShiftLeft,
+    DeleteBlock,
+    BlockInsert,
+    /// `~`, the chars under the cursor in normal mode or the selection in visual mode
+    ToggleCase,
+    /// Lowercases the visual selection
+    Lowercase,
+    /// Uppercases the visual selection
+    Uppercase,
+    /// Does nothing, for unbinding a default key
+    Nop,
+}
+
+impl Action {
+    /// Whether it changes the text or starts typing, which `--readonly` doesn't allow
+    pub fn edits(self) -> bool {
+        matches!(
+            self,
+            Self::Insert
+                | Self::Append
+                | Self::AppendToLine
+                | Self::InsertAtFirstNonBlank
+                | Self::OpenBelow
+                | Self::OpenAbove
+                | Self::ReplaceMode
+                | Self::DeleteChar
+                | Self::DeleteCharBefore
+                | Self::SubstituteChar
+                | Self::DeleteToLineEnd
+                | Self::ChangeToLineEnd
+                | Self::PasteAfter
+                | Self::PasteBefore
+                | Self::JoinLines
+                | Self::BlankLineBelow
+                | Self::BlankLineAbove
+                | Self::ShiftRight
+                | Self::ShiftLeft
+                | Self::DeleteBlock
+                | Self::BlockInsert
+                | Self::ToggleCase
+                | Self::Lowercase
+                | Self::Uppercase
+        )
+    }
+}
+
+/// A key as written in a keymap file, shift is part of the char so only control is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    /// A single char like `k`, a name like `Up` or `PageDown`, `C-` in front holds control
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ctrl, name) = match s.strip_prefix("C-") {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, s),
+        };
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match name.to_ascii_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                _ => bail!("unknown key `{s}`"),
+            },
+        };
+        Ok(Self { code, ctrl })
+    }
+}
+
+/// A key sequence like `g g` or `] Space`, keys are separated by blanks. A lone blank is the
+/// space key
+fn parse_keys(s: &str) -> anyhow::Result<Vec<Key>> {
+    if s.chars().count() == 1 {
+        return Ok(vec![s.parse()?]);
+    }
+    let keys = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<anyhow::Result<Vec<Key>>>()?;
+    if keys.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(keys)
+}
+
+/// Layout of a keymap file, one table of `key = "action"` per mode
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    #[serde(default)]
+    normal: HashMap<String, Action>,
+    #[serde(default)]
+    visual: HashMap<String, Action>,
+    #[serde(default)]
+    visual_block: HashMap<String, Action>,
+    #[serde(default)]
+    insert: HashMap<String, Action>,
+}
+
+/// Which action each key sequence runs, per mode
+#[derive(Debug)]
+pub struct Keymap {
+    pub normal: HashMap<Vec<Key>, Action>,
+    pub visual: HashMap<Vec<Key>, Action>,
+    pub visual_block: HashMap<Vec<Key>, Action>,
+    pub insert: HashMap<Vec<Key>, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            normal: HashMap::new(),
+            visual: HashMap::new(),
+            visual_block: HashMap::new(),
+            insert: HashMap::new(),
+        };
+        keymap
+            .merge(include_str!("../../keymap/default.toml"))
+            .expect("default keymap is valid");
+        keymap
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the bindings of the file at `path` on top
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        let mut keymap = Self::default();
+        keymap
+            .merge(&text)
+            .with_context(|| format!("invalid keymap {path}"))?;
+        Ok(keymap)
+    }
+
+    /// Adds the bindings in `text`, replacing any the keys already had
+    pub fn merge(&mut self, text: &str) -> anyhow::Result<()> {
+        let file: KeymapFile = toml::from_str(text)?;
+        for (bindings, map) in [
+            (file.normal, &mut self.normal),
+            (file.visual, &mut self.visual),
+            (file.visual_block, &mut self.visual_block),
+            (file.insert, &mut self.insert),
+        ] {
+            for (key, action) in bindings {
+                map.insert(
+                    parse_keys(&key).with_context(|| format!("invalid key `{key}`"))?,
+                    action,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Bindings of `mode`, command line modes have no keymap
+    fn mode_map(&self, mode: EditorMode) -> Option<&HashMap<Vec<Key>, Action>> {
+        match mode {
+            EditorMode::Normal => Some(&self.normal),
+            EditorMode::Visual => Some(&self.visual),
+            EditorMode::VisualBlock => Some(&self.visual_block),
+            EditorMode::Insert => Some(&self.insert),
+            EditorMode::Replace | EditorMode::Command | EditorMode::Search => None,
+        }
+    }
+
+    /// Action bound to exactly `keys` in `mode`
+    pub fn get(&self, mode: EditorMode, keys: &[Key]) -> Option<Action> {
+        self.mode_map(mode)?.get(keys).copied()
+    }
+
+    /// Whether some longer sequence in `mode` starts with `keys`, so more keys have to be
+    /// waited for before picking an action
+    pub fn is_prefix(&self, mode: EditorMode, keys: &[Key]) -> bool {
+        self.mode_map(mode).is_some_and(|map| {
+            map.keys()
+                .any(|seq| seq.len() > keys.len() && seq.starts_with(keys))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        let key = |s: &str| s.parse::<Key>().unwrap();
+        assert_eq!(
+            key("k"),
+            Key {
+                code: KeyCode::Char('k'),
+                ctrl: false
+            }
+        );
+        assert_eq!(key("PageDown").code, KeyCode::PageDown);
+        assert_eq!(key("space").code, KeyCode::Char(' '));
+        assert_eq!(
+            key("C-d"),
+            Key {
+                code: KeyCode::Char('d'),
+                ctrl: true
+            }
+        );
+        assert_eq!(key("-").code, KeyCode::Char('-'));
+        assert!("Upp".parse::<Key>().is_err());
+
+        assert_eq!(parse_keys(" ").unwrap(), [key("Space")]);
+        assert_eq!(parse_keys("g g").unwrap(), [key("g"), key("g")]);
+        assert_eq!(parse_keys("] Space").unwrap(), [key("]"), key(" ")]);
+        assert!(parse_keys("gg").is_err());
+        assert!(parse_keys("  ").is_err());
+    }
+
+    #[test]
+    fn test_merge_over_defaults() {
+        let mut keymap = Keymap::default();
+        keymap
+            .merge("[normal]\nUp = \"cursor_up\"\nx = \"nop\"\n[insert]\nDown = \"cursor_down\"")
+            .unwrap();
+        let get = |mode, code| keymap.get(mode, &[Key::from(KeyEvent::from(code))]);
+        assert_eq!(get(EditorMode::Normal, KeyCode::Up), Some(Action::CursorUp));
+        assert_eq!(
+            get(EditorMode::Normal, KeyCode::Char('x')),
+            Some(Action::Nop)
+        );
+        assert_eq!(
+            get(EditorMode::Normal, KeyCode::Char('k')),
+            Some(Action::CursorUp)
+        );
+        assert_eq!(
+            get(EditorMode::Insert, KeyCode::Down),
+            Some(Action::CursorDown)
+        );
+        assert_eq!(get(EditorMode::Command, KeyCode::Char('k')), None);
+
+        keymap.merge("[normal]\n\"g x\" = \"nop\"").unwrap();
+        let g = [Key::from(KeyEvent::from(KeyCode::Char('g')))];
+        assert!(keymap.is_prefix(EditorMode::Normal, &g));
+        assert_eq!(keymap.get(EditorMode::Normal, &g), None);
+        assert!(!keymap.is_prefix(EditorMode::Visual, &g));
+
+        assert!(keymap.merge("[normal]\nk = \"fly\"").is_err());
+        assert!(keymap.merge("[nromal]\nk = \"cursor_up\"").is_err());
+    }
+}
@@ -1,27 +1,55 @@
+pub mod buffer;
+pub mod config;
 pub mod cursor_actions;
+pub mod keymap;
+pub mod search;
+pub mod settings;
+pub mod substitute;
 pub mod text_actions;
 pub mod text_colour;
+pub mod text_objects;
 
+use anyhow::{Context, bail};
+use ratatui::layout::Constraint;
+use ratatui::layout::Layout;
 use ratatui::layout::Rect;
 use ratatui::style::Color;
+use ratatui::style::Modifier;
 use ratatui::style::Style;
 use ratatui::symbols::border;
 use ratatui::text::Line;
 use ratatui::widgets::StatefulWidget;
 use ratatui::widgets::Widget;
+use ropey::Rope;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::read_to_string;
-use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::editor;
-use crate::editor::cursor_actions::CursorAction;
+use crate::editor::buffer::{Buffer, FileFormat, normalize_line_endings};
+use crate::editor::config::Config;
+use crate::editor::cursor_actions::{CursorAction, WORD_PATTERN, next_grapheme, prev_grapheme};
+use crate::editor::keymap::{Action, Key, Keymap};
+use crate::editor::search::SearchAction;
+use crate::editor::settings::{Settings, UnknownOption};
+use crate::editor::substitute::Substitution;
 use crate::editor::text_actions::TextAction;
+use crate::editor::text_objects::TextObject;
 
 use crate::{
-    editor::text_colour::{RUST_SYNTAX, SyntaxRegex, colour_text},
-    theme::ColourTheme,
+    editor::text_colour::{
+        Syntax, SyntaxRegex, builtin_syntax, colour_text, detect_syntax, expand_tabs,
+        expanded_widths, lang_to_extension, patch_range_style, show_invisibles, skip_columns,
+        wrap_line, wrap_starts,
+    },
+    theme::{Colour, ColourTheme},
 };
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use fancy_regex::Regex;
+use crossterm::cursor::SetCursorStyle;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
 use ratatui::{
     DefaultTerminal, Frame,
     widgets::{Block, BorderType, Paragraph},
@@ -35,44 +63,373 @@ pub enum CursorDirection {
     Right,
 }
 
+/// Name shown for a buffer opened without a path
+pub const SCRATCH_BUFFER: &str = "[scratch]";
+
+/// Name shown for a buffer read from stdin
+pub const STDIN_BUFFER: &str = "[stdin]";
+
+/// Largest count a normal mode command can be prefixed with, keeps `99999999j` from spinning
+pub const MAX_COUNT: usize = 9999;
+
 #[derive(Default, Debug)]
 pub struct Editor {
     pub cursor: Position,
     pub mode: EditorMode,
-    pub file_text: String,
+    pub file_text: Rope,
     pub file_path: String,
-    pub keyhistory: Vec<KeyCode>,
+    /// Open files, the shown one's slot is a placeholder, see [`Buffer`]
+    pub buffers: Vec<Buffer>,
+    /// Which of `buffers` is shown
+    pub buffer_index: usize,
+    /// `--readonly`, edits and writes are refused with a warning
+    pub read_only: bool,
     pub exit: bool,
     pub command: String,
     pub frame_area: Rect,
     pub scroll: Position,
     pub theme_path: String,
-    pub message_queue: LogMessage,
+    /// Parsed theme, loaded by [`Editor::set_theme`] instead of on every frame
+    pub theme: ColourTheme,
+    /// Modification time of `theme_path` when it was last read, for `:set autoreload`
+    pub theme_mtime: Option<SystemTime>,
+    /// Parsed syntax, reloaded by [`Editor::reload_syntax`] when the file or `:set syntax` changes
+    pub syntax: Syntax,
+    /// Message on the bottom row and when it was logged, info and warnings go away after
+    /// `messagetimeout`
+    pub message: Option<(LogMessage, Instant)>,
+    pub anchor: Position,
+    pub block_insert: Option<BlockInsert>,
+    pub settings: Settings,
+    /// Keys bound to actions, the built-in one unless `--keymap` gives a file
+    pub keymap: Keymap,
+    /// Syntax for file extensions from the config file, like `h` to `c`
+    pub filetypes: HashMap<String, String>,
+    /// Keys typed so far of a sequence in the keymap, like the first `g` of `gg`
+    pub pending_keys: Vec<event::KeyEvent>,
+    /// When the last of `pending_keys` was typed, they give up after `timeoutlen`
+    pub pending_keys_at: Option<Instant>,
+    /// Lines selected when `:` was typed in visual mode, `:sort` only sorts those
+    pub command_lines: Option<(usize, usize)>,
+    /// Regex of the last search, compiled once rather than on every frame
+    pub last_search: Option<fancy_regex::Regex>,
+    pub search_highlight: bool,
+    pub pending: Option<Pending>,
+    pub pending_count: Option<usize>,
+    pub last_find: Option<CharFind>,
+    pub detected_syntax: Option<String>,
+    /// Bumped on every edit to `file_text`
+    pub revision: usize,
+    /// Whether there are edits that haven't been saved yet
+    pub modified: bool,
+    /// Line break the file is saved with, detected when it's opened
+    pub file_format: FileFormat,
+    /// The file didn't end in a line break when it was opened, saving leaves it off
+    pub no_final_newline: bool,
+    /// What `.` replays
+    pub last_change: Option<LastChange>,
+    /// Keys of the normal mode command being typed, kept if it turns out to change the text
+    pub recording_change: Option<LastChange>,
+    /// Keys recorded with `q<register>`, replayed with `@<register>`
+    pub macros: HashMap<char, Vec<event::KeyEvent>>,
+    /// Register being recorded into and the keys so far
+    pub recording_macro: Option<(char, Vec<event::KeyEvent>)>,
+    /// Registers of the macros currently being replayed, innermost last
+    pub replaying_macros: Vec<char>,
+    /// Positions saved with `m<mark>`
+    pub marks: HashMap<char, Position>,
+    /// Text deleted or yanked last, `p` and `P` paste it
+    pub register: Register,
+    /// Chars overwritten in replace mode so Backspace can put them back, `None` where it appended
+    pub replaced: Vec<Option<char>>,
+    /// Cursor shape last sent to the terminal, changed when the mode needs a different one
+    pub cursor_style: Option<SetCursorStyle>,
 }
 #[derive(Default, Debug, Eq, PartialEq, Clone, Copy)]
 pub enum EditorMode {
     #[default]
     Normal,
     Visual,
+    VisualBlock,
     Insert,
+    /// `R`, typed chars overwrite the ones under the cursor
+    Replace,
     Command,
+    Search,
+}
+
+impl EditorMode {
+    /// Name shown in the status line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Visual => "VISUAL",
+            Self::VisualBlock => "V-BLOCK",
+            Self::Insert => "INSERT",
+            Self::Replace => "REPLACE",
+            Self::Command => "COMMAND",
+            Self::Search => "SEARCH",
+        }
+    }
+
+    /// Terminal cursor shape: a bar while inserting, an underline while replacing, else a block
+    pub fn cursor_style(self) -> SetCursorStyle {
+        match self {
+            Self::Insert => SetCursorStyle::BlinkingBar,
+            Self::Replace => SetCursorStyle::BlinkingUnderScore,
+            Self::Normal | Self::Visual | Self::VisualBlock | Self::Command | Self::Search => {
+                SetCursorStyle::BlinkingBlock
+            }
+        }
+    }
+}
+
+/// A normal mode command that changed the text, plus everything typed if it entered insert mode
+#[derive(Debug, Default, Clone)]
+pub struct LastChange {
+    pub count: Option<usize>,
+    pub keys: Vec<event::KeyEvent>,
+    /// `revision` when the command started
+    pub revision: usize,
+}
+
+/// Normal mode command waiting for the character typed after it
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Pending {
+    Find {
+        forward: bool,
+        till: bool,
+        count: Option<usize>,
+    },
+    /// `r`, replaces `count` chars under the cursor with the next key
+    Replace { count: Option<usize> },
+    /// `m`, waiting for the mark to set
+    SetMark,
+    /// `` ` `` or `'`, waiting for the mark to jump to. `'` only goes to the start of its line
+    JumpToMark { exact: bool },
+    /// `q`, waiting for the register to record into
+    Record,
+    /// `@`, waiting for the register to replay
+    Replay { count: Option<usize> },
+    /// `g` that didn't start a sequence in the keymap, waiting for the `u`/`U` of `gu`/`gU`
+    G { count: Option<usize> },
+    /// `d`, `c` or `y`, waiting for the motion to operate over
+    Operator {
+        operator: Operator,
+        count: Option<usize>,
+        /// A `g` was typed, waiting for the second one of `gg`
+        g: bool,
+    },
+    /// `i` or `a` after an operator, waiting for the text object like `w`, `"` or `(`
+    TextObject { operator: Operator, around: bool },
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Delete,
+    /// Deletes and then enters insert mode
+    Change,
+    Yank,
+    /// `>`, shifts every line the motion touches right
+    Indent,
+    /// `<`, shifts every line the motion touches left
+    Dedent,
+    /// `gu`, lowercases what the motion covers
+    Lowercase,
+    /// `gU`, uppercases what the motion covers
+    Uppercase,
+}
+
+impl Operator {
+    pub fn from_key(key: char) -> Option<Self> {
+        match key {
+            'd' => Some(Self::Delete),
+            'c' => Some(Self::Change),
+            'y' => Some(Self::Yank),
+            '>' => Some(Self::Indent),
+            '<' => Some(Self::Dedent),
+            'u' => Some(Self::Lowercase),
+            'U' => Some(Self::Uppercase),
+            _ => None,
+        }
+    }
+
+    /// Key that starts the operator, typing it twice operates on whole lines like `dd`
+    pub fn key(self) -> char {
+        match self {
+            Self::Delete => 'd',
+            Self::Change => 'c',
+            Self::Yank => 'y',
+            Self::Indent => '>',
+            Self::Dedent => '<',
+            Self::Lowercase => 'u',
+            Self::Uppercase => 'U',
+        }
+    }
+}
+
+/// How `~`, `gu` and `gU` change the case of text
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum CaseChange {
+    Lower,
+    Upper,
+    Toggle,
+}
+
+impl CaseChange {
+    /// Changes the case of every char in `text`, which can change its length like `ß` to `SS`
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Lower => text.to_lowercase(),
+            Self::Upper => text.to_uppercase(),
+            Self::Toggle => text
+                .chars()
+                .flat_map(|c| {
+                    if c.is_lowercase() {
+                        c.to_uppercase().collect::<Vec<_>>()
+                    } else {
+                        c.to_lowercase().collect()
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What an operator covers once its motion has run
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum MotionRange {
+    /// From the first position up to but not including the second
+    Chars(Position, Position),
+    /// First to last line, both included
+    Lines(usize, usize),
+}
+
+/// Text deleted or yanked last
+#[derive(Default, Debug, Eq, PartialEq, Clone)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, pasted below or above the cursor line instead of inside it
+    pub linewise: bool,
+}
+
+/// An `f`, `F`, `t` or `T` motion, kept around so `;` and `,` can repeat it
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct CharFind {
+    pub forward: bool,
+    /// Stop one character before the target like `t`/`T`
+    pub till: bool,
+    pub target: char,
+}
+
+/// Display column and line range of a visual block `I`, the text typed on the top line
+/// gets repeated on every other line once insert mode is left
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct BlockInsert {
+    pub col: usize,
+    pub top: usize,
+    pub bottom: usize,
 }
 
 #[derive(Default, Debug, Eq, PartialEq, Clone, Copy)]
 pub struct Position {
-    pub x: u16,
-    pub y: u16,
+    /// Char index into the line, the cursor moves so it's always on the first char of a
+    /// grapheme cluster
+    pub x: usize,
+    pub y: usize,
 }
 
 impl Editor {
-    pub fn new(path: Option<String>) -> Self {
+    /// Opens every file in `paths` and shows the first, no paths is a scratch buffer
+    pub fn new(paths: Vec<String>) -> Self {
         let mut res = Self::default();
-        res.open_new_file(path);
+        res.load_config();
+        let mut paths = paths.into_iter();
+        res.open_new_file(paths.next());
+        res.buffers = std::iter::once(Buffer::default())
+            .chain(paths.map(|path| Buffer::open(Some(path))))
+            .collect();
+        res
+    }
+    /// A buffer holding text piped in on stdin, it has no path until saved with `:w <path>`
+    pub fn from_stdin(text: String) -> Self {
+        let (text, file_format, no_final_newline) = normalize_line_endings(text);
+        let mut res = Self {
+            file_path: STDIN_BUFFER.into(),
+            detected_syntax: detect_syntax(&text),
+            file_text: text.into(),
+            file_format,
+            no_final_newline,
+            ..Default::default()
+        };
+        res.load_config();
+        res.reload_syntax();
         res
     }
+
+    /// Applies the config file if there is one, a broken one is reported and the built-in
+    /// defaults stay
+    pub fn load_config(&mut self) {
+        let Some(path) = Config::path() else {
+            return;
+        };
+        match Config::load(&path) {
+            Ok(config) => self.apply_config(config),
+            Err(e) => self.log(LogMessage::Error(format!("{e:#}"))),
+        }
+    }
+
+    pub fn apply_config(&mut self, config: Config) {
+        self.settings = config.set;
+        self.filetypes = config.filetypes;
+        if let Some(theme) = config.theme
+            && let Err(e) = self.set_theme(Some(theme))
+        {
+            self.log(LogMessage::Error(format!("{e:#}")));
+        }
+    }
+
+    /// `:mkconfig`, writes the current options, theme and filetypes to the config file. It
+    /// won't replace one that's there without a `!`
+    pub fn write_config(&mut self, force: bool) {
+        let Some(path) = Config::path() else {
+            return self.log(LogMessage::Error("no config folder, HOME isn't set".into()));
+        };
+        if path.exists() && !force {
+            return self.log(LogMessage::Error(format!(
+                "{} exists, use mkconfig! to overwrite",
+                path.display()
+            )));
+        }
+        let config = Config {
+            theme: self
+                .theme_path
+                .strip_prefix("theme/")
+                .and_then(|name| name.strip_suffix(".toml"))
+                .map(str::to_string),
+            set: Settings {
+                syntax: None,
+                ..self.settings.clone()
+            },
+            filetypes: self.filetypes.clone(),
+        };
+        match config.save(&path) {
+            Ok(()) => self.log(LogMessage::Info(format!("wrote {}", path.display()))),
+            Err(e) => self.log(LogMessage::Error(format!("{e:#}"))),
+        }
+    }
+
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
         while !self.exit {
             self.frame_area = terminal.get_frame().area();
+            self.update_scroll();
+            self.reload_theme_if_changed();
+            self.expire_message();
+            let style = self.mode.cursor_style();
+            if self.cursor_style != Some(style) {
+                execute!(terminal.backend_mut(), style)?;
+                self.cursor_style = Some(style);
+            }
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events()?;
         }
@@ -80,14 +437,42 @@ impl Editor {
     }
 
     pub fn draw(&self, frame: &mut Frame) {
-        frame.set_cursor_position((self.cursor.x + 1, self.cursor.y + 1));
+        // leaving the position unset hides the terminal cursor
+        if let Some(position) = self.cursor_screen_position() {
+            frame.set_cursor_position(position);
+        }
 
         frame.render_stateful_widget(self, frame.area(), &mut State);
     }
 
     pub fn handle_events(&mut self) -> std::io::Result<()> {
+        // with autoreload on, redraw now and then even without input so theme edits show up,
+        // and wake up when a half typed key sequence or a message times out
+        let timeout = [
+            self.settings
+                .autoreload
+                .then_some(Duration::from_millis(250)),
+            self.pending_keys_timeout(),
+            self.message_timeout(),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        if let Some(timeout) = timeout
+            && !event::poll(timeout)?
+        {
+            if self
+                .pending_keys_timeout()
+                .is_some_and(|left| left.is_zero())
+            {
+                self.time_out_pending_keys();
+            }
+            return Ok(());
+        }
         match event::read()? {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                // a message stays up until the next key, whatever that key logs replaces it
+                self.message = None;
                 self.handle_key_event(key_event);
             }
             Event::Resize(x, y) => self.handle_resize(x, y),
@@ -106,25 +491,166 @@ impl Editor {
 
     /// Opens `[scratch]` buffer if no path is provided
     pub fn open_new_file(&mut self, path: Option<String>) {
-        let Some(path) = path else {
-            self.file_text = String::new();
-            self.file_path = "[scratch]".into();
+        let mut buffer = Buffer::open(path);
+        self.swap_buffer(&mut buffer);
+        self.clamp_cursor();
+        self.reload_syntax();
+    }
+
+    /// Shows buffer `index`, the one shown before keeps its text, cursor and unsaved changes
+    pub fn switch_buffer(&mut self, index: usize) {
+        if index == self.buffer_index || index >= self.buffers.len() {
             return;
+        }
+        let mut buffer = std::mem::take(&mut self.buffers[index]);
+        self.swap_buffer(&mut buffer);
+        self.buffers[self.buffer_index] = buffer;
+        self.buffer_index = index;
+        self.reload_syntax();
+    }
+
+    /// `:bn` and `:bp`, the next or previous buffer wrapping around at the ends
+    pub fn cycle_buffer(&mut self, forward: bool) {
+        let len = self.buffers.len().max(1);
+        let step = if forward { 1 } else { len - 1 };
+        self.switch_buffer((self.buffer_index + step) % len);
+    }
+
+    /// Buffers other than the shown one, whose slot only holds a placeholder
+    fn hidden_buffers(&self) -> impl Iterator<Item = &Buffer> {
+        let shown = self.buffer_index;
+        self.buffers
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, buffer)| (index != shown).then_some(buffer))
+    }
+
+    /// Trades the shown buffer's state with `buffer`. Destructured so a field added to
+    /// [`Buffer`] has to be swapped here too
+    fn swap_buffer(&mut self, buffer: &mut Buffer) {
+        let Buffer {
+            file_text,
+            file_path,
+            cursor,
+            scroll,
+            modified,
+            detected_syntax,
+            marks,
+            file_format,
+            no_final_newline,
+        } = buffer;
+        std::mem::swap(&mut self.file_text, file_text);
+        std::mem::swap(&mut self.file_path, file_path);
+        std::mem::swap(&mut self.cursor, cursor);
+        std::mem::swap(&mut self.scroll, scroll);
+        std::mem::swap(&mut self.modified, modified);
+        std::mem::swap(&mut self.detected_syntax, detected_syntax);
+        std::mem::swap(&mut self.marks, marks);
+        std::mem::swap(&mut self.file_format, file_format);
+        std::mem::swap(&mut self.no_final_newline, no_final_newline);
+    }
+
+    /// Loads `./syntax/{lang}.toml` for the current syntax, falling back to the built in
+    /// syntaxes and then to plain text
+    pub fn reload_syntax(&mut self) {
+        let lang = lang_to_extension(self.syntax_lang());
+        self.syntax = read_to_string(format!("./syntax/{lang}.toml"))
+            .ok()
+            .and_then(|syntax| toml::from_str::<SyntaxRegex>(&syntax).ok())
+            .or_else(|| builtin_syntax(lang).cloned())
+            .map_or(Syntax::Plain, |syntax| Syntax::Regex(Box::new(syntax)));
+    }
+
+    /// Syntax used for highlighting, `:set syntax` wins over a detected shebang or modeline
+    /// which wins over the file extension
+    pub fn syntax_lang(&self) -> &str {
+        self.settings
+            .syntax
+            .as_deref()
+            .or(self.detected_syntax.as_deref())
+            .unwrap_or_else(|| {
+                let ext = self.file_path.split('.').next_back().unwrap_or_default();
+                self.filetypes.get(ext).map_or(ext, String::as_str)
+            })
+    }
+
+    /// `:w`, saving to `path` from now on if one is given. Buffers without a file need a path
+    pub fn write(&mut self, path: Option<&str>) -> bool {
+        if let Some(path) = path {
+            self.file_path = path.to_string();
+            self.reload_syntax();
+        }
+        if matches!(self.file_path.as_str(), SCRATCH_BUFFER | STDIN_BUFFER) {
+            self.log(LogMessage::Error("no file name, use :w <path>".into()));
+            return false;
+        }
+        match self.save_file() {
+            Ok(()) => true,
+            Err(e) => {
+                self.log(LogMessage::Error(format!("{e:#}")));
+                false
+            }
+        }
+    }
+
+    /// `:wq`, only exits once the buffer was written
+    pub fn write_and_exit(&mut self, path: Option<&str>) {
+        if self.write(path) {
+            self.exit();
+        }
+    }
+
+    pub fn save_file(&mut self) -> anyhow::Result<()> {
+        if let Some(dir) = Path::new(&self.file_path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let file = std::fs::File::create(self.file_path.as_str())
+            .with_context(|| format!("failed to open {}", self.file_path))?;
+        self.write_text(std::io::BufWriter::new(file))
+            .with_context(|| format!("failed to write to {}", self.file_path))?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Writes the text with the line breaks of `file_format`, ending in one unless the file
+    /// didn't when it was opened
+    fn write_text(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        let len = self.file_text.len_chars();
+        let ends_with_newline = self.ends_with_newline();
+        let text = if self.no_final_newline && ends_with_newline {
+            self.file_text.slice(..len - 1)
+        } else {
+            self.file_text.slice(..)
         };
-        self.file_path.clone_from(&path);
-        match read_to_string(path) {
-            Ok(content) => {
-                self.file_text = content;
+        let line_break = self.file_format.line_break();
+        for chunk in text.chunks() {
+            match self.file_format {
+                FileFormat::Unix => writer.write_all(chunk.as_bytes())?,
+                FileFormat::Dos => writer.write_all(chunk.replace('\n', line_break).as_bytes())?,
             }
-            Err(_) => self.file_text = String::new(),
         }
+        if !self.no_final_newline && len > 0 && !ends_with_newline {
+            writer.write_all(line_break.as_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Whether the text ends in a line break, an empty text doesn't
+    pub fn ends_with_newline(&self) -> bool {
+        self.file_text
+            .len_chars()
+            .checked_sub(1)
+            .is_some_and(|last| self.file_text.char(last) == '\n')
     }
 
-    pub fn save_file(&self) {
-        let mut file =
-            std::fs::File::create(self.file_path.as_str()).expect("directory does not exist");
-        file.write_all(self.file_text.as_bytes())
-            .expect("failed to write to file");
+    /// Records an edit to `file_text`
+    pub fn mark_modified(&mut self) {
+        self.revision += 1;
+        self.modified = true;
     }
 
     pub fn exit(&mut self) {
@@ -132,92 +658,229 @@ impl Editor {
     }
 
     pub fn handle_key_event(&mut self, key_event: event::KeyEvent) {
+        if let Some((_, keys)) = &mut self.recording_macro
+            && self.replaying_macros.is_empty()
+        {
+            keys.push(key_event);
+        }
+        if self.mode == EditorMode::Normal
+            && self.pending.is_none()
+            && self.pending_keys.is_empty()
+            && key_event.code == KeyCode::Char('.')
+        {
+            let count = self.pending_count.take();
+            self.recording_change = None;
+            self.repeat_last_change(count);
+        } else {
+            self.record_change_key(key_event);
+            self.dispatch_key_event(key_event);
+            self.finish_change();
+        }
+        // deleting the end of the file can leave the cursor below the last line
+        if self.mode == EditorMode::Normal {
+            self.clamp_cursor();
+        }
+    }
+
+    /// Remembers `key_event` as part of the command being typed, count digits go into its count
+    fn record_change_key(&mut self, key_event: event::KeyEvent) {
+        match self.mode {
+            EditorMode::Normal => {
+                if self.is_count_digit(key_event) {
+                    return;
+                }
+                let change = self.recording_change.get_or_insert_with(|| LastChange {
+                    count: self.pending_count,
+                    keys: Vec::new(),
+                    revision: self.revision,
+                });
+                change.keys.push(key_event);
+            }
+            EditorMode::Insert | EditorMode::Replace => {
+                if let Some(change) = &mut self.recording_change {
+                    change.keys.push(key_event);
+                }
+            }
+            _ => self.recording_change = None,
+        }
+    }
+
+    /// Once the command is done (and any insert it started has ended) keeps it for `.`
+    /// if the text changed
+    fn finish_change(&mut self) {
         match self.mode {
+            EditorMode::Normal if self.pending.is_some() || !self.pending_keys.is_empty() => {}
             EditorMode::Normal => {
-                if let KeyCode::Char(c) = key_event.code {
+                if let Some(change) = self.recording_change.take()
+                    && change.revision != self.revision
+                {
+                    self.last_change = Some(change);
+                }
+            }
+            EditorMode::Insert | EditorMode::Replace => {}
+            _ => self.recording_change = None,
+        }
+    }
+
+    /// Replays the last change at the cursor, `count` replaces the count it was typed with
+    pub fn repeat_last_change(&mut self, count: Option<usize>) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        self.pending_count = count.or(change.count);
+        for key_event in change.keys {
+            self.handle_key_event(key_event);
+        }
+    }
+
+    /// Whether `key_event` is a digit of the count typed before a normal mode command
+    fn is_count_digit(&self, key_event: event::KeyEvent) -> bool {
+        self.mode == EditorMode::Normal
+            && self.pending.is_none()
+            && self.pending_keys.is_empty()
+            && match key_event.code {
+                KeyCode::Char('1'..='9') => true,
+                KeyCode::Char('0') => self.pending_count.is_some(),
+                _ => false,
+            }
+    }
+
+    /// Looks `key_event` up in the keymap together with the keys typed before it, waiting for
+    /// more while they're the start of a longer sequence
+    fn dispatch_key_event(&mut self, key_event: event::KeyEvent) {
+        if self.pending.is_some() || self.is_count_digit(key_event) {
+            self.handle_unmapped_key(key_event);
+            return;
+        }
+        self.pending_keys.push(key_event);
+        let keys: Vec<Key> = self.pending_keys.iter().map(|&e| Key::from(e)).collect();
+        if self.keymap.is_prefix(self.mode, &keys) {
+            self.pending_keys_at = Some(Instant::now());
+            return;
+        }
+        self.resolve_pending_keys();
+    }
+
+    /// Runs the longest sequence bound at the start of `pending_keys`, or the first key on its
+    /// own if there's none, and types the keys after it again
+    fn resolve_pending_keys(&mut self) {
+        let pending = std::mem::take(&mut self.pending_keys);
+        self.pending_keys_at = None;
+        let keys: Vec<Key> = pending.iter().map(|&e| Key::from(e)).collect();
+        let mapped = (1..=keys.len())
+            .rev()
+            .find_map(|len| Some((len, self.keymap.get(self.mode, &keys[..len])?)));
+        let used = match mapped {
+            Some((len, action)) => {
+                let count = self.pending_count.take();
+                self.run_action(action, count);
+                len
+            }
+            None => {
+                self.handle_unmapped_key(pending[0]);
+                1
+            }
+        };
+        for &key_event in &pending[used..] {
+            self.dispatch_key_event(key_event);
+        }
+    }
+
+    /// How long until the keys of a half typed sequence time out, `None` when there are none
+    fn pending_keys_timeout(&self) -> Option<Duration> {
+        let at = self.pending_keys_at?;
+        Some(Duration::from_millis(self.settings.timeoutlen as u64).saturating_sub(at.elapsed()))
+    }
+
+    /// Gives up waiting for the rest of a sequence and runs what the keys so far make up
+    pub fn time_out_pending_keys(&mut self) {
+        if self.pending_keys.is_empty() {
+            return;
+        }
+        self.resolve_pending_keys();
+        self.finish_change();
+    }
+
+    /// Keys with nothing bound to them in the keymap, and anything typed while a command
+    /// waits for its character
+    fn handle_unmapped_key(&mut self, key_event: event::KeyEvent) {
+        match self.mode {
+            EditorMode::Normal if self.pending.is_some() => self.handle_pending(key_event),
+            EditorMode::Normal if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_count = None;
+            }
+            EditorMode::Normal => match key_event.code {
+                KeyCode::Char(c @ '1'..='9') => self.push_count_digit(c),
+                KeyCode::Char('0') if self.pending_count.is_some() => self.push_count_digit('0'),
+                KeyCode::Char(c) => {
+                    let count = self.pending_count.take();
                     match c {
-                        'q' => self.exit(),
-                        'i' => self.mode = EditorMode::Insert,
-                        'v' => self.mode = EditorMode::Visual,
-                        ':' => self.mode = EditorMode::Command,
-                        'k' => self.move_cursor(CursorDirection::Up),
-                        'j' => self.move_cursor(CursorDirection::Down),
-                        'h' => self.move_cursor(CursorDirection::Left),
-                        'l' => self.move_cursor(CursorDirection::Right),
-                        'd' => self.remove_char(self.cursor),
-                        'o' => {
-                            self.insert_char(
-                                Position {
-                                    x: u16::try_from(self.line_at_cursor().len())
-                                        .unwrap_or_default()
-                                        + 1,
-                                    y: self.cursor.y,
-                                },
-                                '\n',
-                            );
-                            self.move_cursor(CursorDirection::Down);
-                            self.mode = EditorMode::Insert;
-                        }
-                        'O' => {
-                            self.insert_char(
-                                Position {
-                                    x: u16::try_from(self.line_from_cursor(-1).len())
-                                        .unwrap_or_default()
-                                        + 1,
-                                    y: self.cursor.y - 1,
-                                },
-                                '\n',
-                            );
-                            self.mode = EditorMode::Insert;
+                        'q' => match self.recording_macro.take() {
+                            Some((register, mut keys)) => {
+                                // drop the `q` that stopped the recording
+                                keys.pop();
+                                self.macros.insert(register, keys);
+                            }
+                            None => self.pending = Some(Pending::Record),
+                        },
+                        '@' => self.pending = Some(Pending::Replay { count }),
+                        'm' => self.pending = Some(Pending::SetMark),
+                        '`' | '\'' => {
+                            self.pending = Some(Pending::JumpToMark { exact: c == '`' });
                         }
-                        'A' => {
-                            self.cursor.x =
-                                u16::try_from(self.line_at_cursor().len()).unwrap_or_default();
-                            self.mode = EditorMode::Insert;
+                        'f' | 'F' | 't' | 'T' => {
+                            self.pending = Some(Pending::Find {
+                                forward: c.is_lowercase(),
+                                till: c.eq_ignore_ascii_case(&'t'),
+                                count,
+                            });
                         }
-                        '0' => self.cursor.x = 0,
-                        'e' => self.move_to_end_of_pat(
-                            &Regex::new(r"(\p{Z}+|\p{P}+|\p{N}+|\p{L}+|\p{S}+)").unwrap(),
-                        ),
-                        'b' => self.move_to_start_of_pat(
-                            &Regex::new(r"(\p{Z}+|\p{P}+|\p{N}+|\p{L}+|\p{S}+)").unwrap(),
-                        ),
-                        'g' => {
-                            if let Some(KeyCode::Char('g')) = self.keyhistory.last() {
-                                self.cursor = Position::default();
-                            }
+                        'r' | 'd' | 'c' | '>' | '<' if self.read_only => self.refuse_edit(),
+                        'r' => self.pending = Some(Pending::Replace { count }),
+                        'd' | 'c' | 'y' | '>' | '<' => {
+                            self.pending =
+                                Operator::from_key(c).map(|operator| Pending::Operator {
+                                    operator,
+                                    count,
+                                    g: false,
+                                });
                         }
+                        'g' => self.pending = Some(Pending::G { count }),
                         _ => {}
                     }
                 }
-            }
-            EditorMode::Visual => match key_event.code {
-                KeyCode::Char('v') | KeyCode::Esc => self.mode = EditorMode::Normal,
-                _ => {}
+                _ => self.pending_count = None,
             },
+            EditorMode::Visual | EditorMode::VisualBlock => {}
             EditorMode::Insert => match key_event.code {
-                KeyCode::Char(c) => {
-                    self.insert_char(self.cursor, c);
-                    self.move_cursor(CursorDirection::Right);
+                KeyCode::Char(c) => self.type_char(c),
+                KeyCode::Enter => self.newline(),
+                KeyCode::Tab => self.insert_tab(),
+                KeyCode::BackTab => self.shift_lines(self.cursor.y, self.cursor.y, false),
+                KeyCode::Backspace => self.backspace(),
+                KeyCode::Esc => {
+                    self.finish_block_insert();
+                    self.mode = EditorMode::Normal;
                 }
+                _ => {}
+            },
+            EditorMode::Replace => match key_event.code {
+                KeyCode::Char(c) => self.overwrite_char(c),
                 KeyCode::Enter => {
-                    self.insert_char(self.cursor, '\n');
-                    self.cursor = Position {
-                        x: 0,
-                        y: self.cursor.y + 1,
+                    if !self.cursor_at_end_of_file() {
+                        self.cursor = Position {
+                            x: 0,
+                            y: self.cursor.y + 1,
+                        };
                     }
+                    self.replaced.clear();
                 }
-                KeyCode::Backspace => {
-                    self.remove_char(Position {
-                        x: self.cursor.x - 1,
-                        y: self.cursor.y,
-                    });
-                    self.move_cursor(CursorDirection::Left);
-                }
+                KeyCode::Backspace => self.restore_overwritten_char(),
                 KeyCode::Esc => self.mode = EditorMode::Normal,
                 _ => {}
             },
-            EditorMode::Command => match key_event.code {
+            EditorMode::Command | EditorMode::Search => match key_event.code {
+                KeyCode::Enter if self.mode == EditorMode::Search => self.execute_search(),
                 KeyCode::Enter => self.execute_command(),
                 KeyCode::Esc => self.end_command(),
                 KeyCode::Char(c) => self.command.push(c),
@@ -225,145 +888,3450 @@ impl Editor {
                 _ => {}
             },
         }
-        self.keyhistory.push(key_event.code);
     }
-    pub fn execute_command(&mut self) {
-        match self.command.clone().trim() {
-            "q" => self.exit(),
-            "w" => self.save_file(),
-            "x" => {
-                self.save_file();
-                self.exit();
+
+    /// Runs what a key is bound to in the keymap, `count` is the count typed before it
+    pub fn run_action(&mut self, action: Action, count: Option<usize>) {
+        if self.read_only && action.edits() {
+            return self.refuse_edit();
+        }
+        match action {
+            Action::CursorUp => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Up)),
+            Action::CursorDown => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Down)),
+            Action::CursorLeft => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Left)),
+            Action::CursorRight => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Right)),
+            Action::WordForward => {
+                self.repeat(count, |ed| ed.move_to_start_of_next_pat(&WORD_PATTERN));
             }
-            "e" => self.log(LogMessage::Error("aaaa".into())),
-            path if path.starts_with("theme ") => {
-                self.set_theme(Some(&path["theme ".len()..]));
+            Action::WordBackward => {
+                self.repeat(count, |ed| ed.move_to_start_of_prev_pat(&WORD_PATTERN));
             }
-            _ => {}
+            Action::WordEnd => self.repeat(count, |ed| ed.move_to_end_of_pat(&WORD_PATTERN)),
+            Action::LineStart => self.cursor.x = 0,
+            Action::LineEnd => self.move_to_end_of_line(),
+            Action::FirstNonBlank => self.move_to_first_non_blank(),
+            Action::MatchingBracket => {
+                let found = self.move_to_matching_bracket();
+                if !found {
+                    self.log(LogMessage::Warn("no matching bracket".into()));
+                }
+            }
+            // like vim both land on the first non-blank of the line
+            Action::GotoLine => {
+                self.goto_line(count.map_or(usize::MAX, |n| n.saturating_sub(1)));
+                self.move_to_first_non_blank();
+            }
+            Action::GotoFirstLine => {
+                self.goto_line(count.map_or(0, |n| n.saturating_sub(1)));
+                self.move_to_first_non_blank();
+            }
+            Action::ScreenTop => self.move_to_screen_line('H', count),
+            Action::ScreenMiddle => self.move_to_screen_line('M', count),
+            Action::ScreenBottom => self.move_to_screen_line('L', count),
+            Action::HalfPageDown => self.scroll_half_page(true),
+            Action::HalfPageUp => self.scroll_half_page(false),
+            Action::SearchNext => self.repeat(count, |ed| ed.search_next(true)),
+            Action::SearchPrevious => self.repeat(count, |ed| ed.search_next(false)),
+            Action::RepeatFind | Action::RepeatFindReverse => {
+                if let Some(mut find) = self.last_find {
+                    find.forward ^= action == Action::RepeatFindReverse;
+                    self.repeat(count, |ed| _ = ed.find_char(find, true));
+                }
+            }
+            Action::Insert => self.mode = EditorMode::Insert,
+            Action::Append => {
+                let len = self.line_at_cursor().chars().count();
+                if len > 0 {
+                    self.cursor.x = (self.cursor.x + 1).min(len);
+                }
+                self.mode = EditorMode::Insert;
+            }
+            Action::AppendToLine => {
+                self.cursor.x = self.line_at_cursor().chars().count();
+                self.mode = EditorMode::Insert;
+            }
+            Action::InsertAtFirstNonBlank => {
+                let line = self.line_at_cursor();
+                let x = line
+                    .chars()
+                    .position(|c| !c.is_whitespace())
+                    .unwrap_or_else(|| line.chars().count());
+                self.cursor.x = x;
+                self.mode = EditorMode::Insert;
+            }
+            Action::OpenBelow => self.open_line(true),
+            Action::OpenAbove => self.open_line(false),
+            Action::ReplaceMode => {
+                self.replaced.clear();
+                self.mode = EditorMode::Replace;
+            }
+            Action::Visual => {
+                self.anchor = self.cursor;
+                self.mode = EditorMode::Visual;
+            }
+            Action::VisualBlock => {
+                self.anchor = self.cursor;
+                self.mode = EditorMode::VisualBlock;
+            }
+            Action::Command => {
+                if matches!(self.mode, EditorMode::Visual | EditorMode::VisualBlock) {
+                    let (start, end) = self.block_bounds();
+                    self.command_lines = Some((start.y, end.y));
+                }
+                self.mode = EditorMode::Command;
+            }
+            Action::Search => self.mode = EditorMode::Search,
+            Action::FileStats => self.show_stats(),
+            Action::NormalMode => self.mode = EditorMode::Normal,
+            Action::DeleteChar => self.delete_chars(count, true),
+            Action::DeleteCharBefore => self.delete_chars(count, false),
+            Action::SubstituteChar => {
+                let x = self.cursor.x;
+                self.delete_chars(count, true);
+                let len = self.line_at_cursor().chars().count();
+                self.cursor.x = x.min(len);
+                self.mode = EditorMode::Insert;
+            }
+            Action::DeleteToLineEnd => {
+                self.apply_operator(Operator::Delete, '$', count);
+                let len = self.line_at_cursor().chars().count();
+                self.cursor.x = self.cursor.x.min(len.saturating_sub(1));
+            }
+            Action::ChangeToLineEnd => self.apply_operator(Operator::Change, '$', count),
+            Action::PasteAfter => self.paste(count, true),
+            Action::PasteBefore => self.paste(count, false),
+            Action::JoinLines => {
+                for _ in 0..count.unwrap_or(2).saturating_sub(1).max(1) {
+                    match self.join_lines(self.cursor.y) {
+                        Some(join) => self.cursor = join,
+                        None => {
+                            self.log(LogMessage::Warn("no line below to join".into()));
+                            break;
+                        }
+                    }
+                }
+            }
+            Action::BlankLineBelow => self.repeat(count, |ed| {
+                let x = ed.line_at_cursor().chars().count();
+                ed.insert_char(Position { x, y: ed.cursor.y }, '\n');
+            }),
+            Action::BlankLineAbove => self.repeat(count, |ed| {
+                ed.insert_char(
+                    Position {
+                        x: 0,
+                        y: ed.cursor.y,
+                    },
+                    '\n',
+                );
+                ed.cursor.y += 1;
+            }),
+            Action::ShiftRight => self.shift_selection(true),
+            Action::ShiftLeft => self.shift_selection(false),
+            Action::DeleteBlock => self.delete_block(),
+            Action::BlockInsert => self.start_block_insert(),
+            Action::ToggleCase if self.mode == EditorMode::Normal => self.toggle_case(count),
+            Action::ToggleCase => self.change_selection_case(CaseChange::Toggle),
+            Action::Lowercase => self.change_selection_case(CaseChange::Lower),
+            Action::Uppercase => self.change_selection_case(CaseChange::Upper),
+            Action::Nop => {}
         }
-        self.end_command();
     }
-    pub fn end_command(&mut self) {
-        self.mode = EditorMode::Normal;
-        self.command = String::new();
+
+    /// Splits the line at the cursor, with `autoindent` the new line keeps the indent of the
+    /// current one plus one level more after a `{`, a tab with `noexpandtab`
+    pub fn newline(&mut self) {
+        let mut indent = String::new();
+        if self.settings.autoindent {
+            let before: String = self.line_at_cursor().chars().take(self.cursor.x).collect();
+            indent = before
+                .chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .collect();
+            if before.trim_end().ends_with('{') {
+                if self.settings.expandtab {
+                    indent.push_str(&" ".repeat(self.settings.shiftwidth));
+                } else {
+                    indent.push('\t');
+                }
+            }
+        }
+        self.insert_str(self.cursor, &format!("\n{indent}"));
+        self.cursor = Position {
+            x: indent.chars().count(),
+            y: self.cursor.y + 1,
+        };
     }
-    pub fn set_theme(&mut self, path: Option<impl ToString>) {
-        let path = path.map_or("default".to_string(), |v| v.to_string());
-        let full_path = ["theme", &path].join("/");
-        let full_path = [full_path, "toml".into()].join(".");
-        self.theme_path = full_path;
+
+    /// `o` and `O`, starts a line below or above the cursor line and inserts on it. With
+    /// `autoindent` the new line gets the indent of the cursor line
+    pub fn open_line(&mut self, below: bool) {
+        let line = self.line_at_cursor();
+        let len = line.chars().count();
+        let indent: String = if self.settings.autoindent {
+            line.chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .collect()
+        } else {
+            String::new()
+        };
+        let y = self.cursor.y;
+        let x = indent.chars().count();
+        if below {
+            self.insert_str(Position { x: len, y }, &format!("\n{indent}"));
+            self.cursor = Position { x, y: y + 1 };
+        } else {
+            self.insert_str(Position { x: 0, y }, &format!("{indent}\n"));
+            self.cursor = Position { x, y };
+        }
+        self.mode = EditorMode::Insert;
     }
-    pub fn log(&mut self, msg: LogMessage) {
-        self.message_queue = msg;
+
+    /// Replace mode typing, overwrites the char under the cursor or appends at the end of the line
+    pub fn overwrite_char(&mut self, c: char) {
+        let end = Position {
+            x: self.cursor.x + 1,
+            ..self.cursor
+        };
+        let old = self.line_at_cursor().chars().nth(self.cursor.x);
+        match old {
+            Some(_) => self.replace_range(self.cursor, end, &c.to_string()),
+            None => self.insert_char(self.cursor, c),
+        }
+        self.replaced.push(old);
+        self.cursor = end;
     }
-}
 
-#[derive(Debug)]
-pub enum LogMessage {
-    Error(String),
-    Warn(String),
-    Info(String),
-}
+    /// Backspace in replace mode, steps back and puts back what was overwritten there
+    pub fn restore_overwritten_char(&mut self) {
+        if self.cursor.x == 0 {
+            return;
+        }
+        self.cursor.x -= 1;
+        let end = Position {
+            x: self.cursor.x + 1,
+            ..self.cursor
+        };
+        match self.replaced.pop() {
+            Some(Some(old)) => self.replace_range(self.cursor, end, &old.to_string()),
+            Some(None) => _ = self.delete_range(self.cursor, end),
+            None => {}
+        }
+    }
 
-impl Default for LogMessage {
-    fn default() -> Self {
-        LogMessage::Info(String::new())
+    /// Tab in insert mode, a `\t` or with `expandtab` spaces up to the next tab stop
+    pub fn insert_tab(&mut self) {
+        if !self.settings.expandtab {
+            self.insert_char(self.cursor, '\t');
+            self.cursor.x += 1;
+            return;
+        }
+        let tabwidth = self.settings.tabwidth.max(1);
+        let n = tabwidth - self.display_column() % tabwidth;
+        self.insert_str(self.cursor, &" ".repeat(n));
+        self.cursor.x += n;
     }
-}
 
-impl LogMessage {
-    pub fn to_paragraph(&self) -> Paragraph<'_> {
-        match self {
-            LogMessage::Error(msg) => {
-                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::Red))
+    /// Insert mode typing. With `autopairs` an opening bracket or quote brings its closing one
+    /// along in the same edit, and a closing one already after the cursor is stepped over
+    pub fn type_char(&mut self, c: char) {
+        if self.settings.autopairs {
+            let line = self.line_at_cursor();
+            let x = self.cursor.x;
+            let prev = x.checked_sub(1).and_then(|x| line.chars().nth(x));
+            let next = line.chars().nth(x);
+            if next == Some(c) && matches!(c, ')' | ']' | '}' | '"' | '\'') {
+                self.move_cursor(CursorDirection::Right);
+                return;
             }
+            // a quote right after a word is more likely `don't` than the start of a string
+            let after_word = prev.is_some_and(char::is_alphanumeric);
+            if let Some(close) = closing_pair(c).filter(|_| !(after_word && c == '\'')) {
+                self.insert_str(self.cursor, &format!("{c}{close}"));
+                self.move_cursor(CursorDirection::Right);
+                return;
+            }
+        }
+        self.insert_char(self.cursor, c);
+        self.move_cursor(CursorDirection::Right);
+    }
 
-            LogMessage::Warn(msg) => {
-                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::Yellow))
+    /// Removes the char before the cursor, at the start of a line it joins it onto the previous
+    /// one. With `autopairs` an empty pair like `()` goes all at once
+    pub fn backspace(&mut self) {
+        match self.cursor {
+            Position { x: 0, y: 0 } => {}
+            Position { x: 0, y } => {
+                let join = Position {
+                    x: self.line_at(y - 1).chars().count(),
+                    y: y - 1,
+                };
+                self.delete_range(join, self.cursor);
+                self.cursor = join;
             }
-            LogMessage::Info(msg) => {
-                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::White))
+            Position { x, y } => {
+                let line = self.line_at(y);
+                let mut chars = line.chars().skip(x - 1);
+                let pair = chars.next().and_then(closing_pair);
+                let start = prev_grapheme(&line, x);
+                if self.settings.autopairs && pair.is_some() && pair == chars.next() {
+                    self.delete_range(Position { x: x - 1, y }, Position { x: x + 1, y });
+                } else {
+                    self.remove_char(Position { x: start, y });
+                }
+                self.cursor.x = start;
             }
         }
     }
-}
 
-pub struct State;
+    /// Appends a digit to the count typed before a normal mode command, capped at [`MAX_COUNT`]
+    pub fn push_count_digit(&mut self, digit: char) {
+        let digit = digit.to_digit(10).unwrap_or_default() as usize;
+        self.pending_count = Some(
+            self.pending_count
+                .unwrap_or_default()
+                .saturating_mul(10)
+                .saturating_add(digit)
+                .min(MAX_COUNT),
+        );
+    }
 
-impl StatefulWidget for &Editor {
-    type State = State;
-    fn render(
-        self,
-        area: ratatui::prelude::Rect,
-        buf: &mut ratatui::prelude::Buffer,
-        _state: &mut editor::State,
-    ) where
-        Self: Sized,
-    {
-        let theme = read_to_string(self.theme_path.as_str())
-            .unwrap_or(include_str!("../../theme/default.toml").to_string());
-        let theme: ColourTheme = toml::from_str(&theme).unwrap();
-
-        let syntax_lang = self.file_path.split('.').next_back().unwrap_or_default();
-        let syntax_path = format!("./syntax/{syntax_lang}.toml");
-        let syntax = read_to_string(syntax_path);
-        let syntax: SyntaxRegex = syntax
-            .map(|syntax| toml::from_str::<SyntaxRegex>(&syntax).unwrap_or(RUST_SYNTAX.clone()))
-            .unwrap_or(RUST_SYNTAX.clone());
-
-        let title = Line::from(self.file_path.as_str());
-        let mode = Line::from(format!("{:#?}", self.mode));
-        let block = Block::bordered()
-            .border_type(BorderType::Rounded)
-            .title(title.centered())
-            .title_bottom(mode.left_aligned())
-            .style(Style::new().bg(theme.background.into()))
-            .border_set(border::THICK);
-        let text = self.file_text.as_str();
-        let text = colour_text(text, &theme, &syntax);
+    /// Runs `action` `count` times, once if no count was typed
+    pub fn repeat(&mut self, count: Option<usize>, mut action: impl FnMut(&mut Self)) {
+        for _ in 0..count.unwrap_or(1) {
+            action(self);
+        }
+    }
 
-        let adjusted_area = area;
+    /// Moves to `mark`, clamped to the buffer in case it shrank since the mark was set
+    pub fn jump_to_mark(&mut self, mark: char, exact: bool) {
+        let Some(&pos) = self.marks.get(&mark) else {
+            return self.log(LogMessage::Warn(format!("mark {mark} not set")));
+        };
+        self.cursor.x = if exact { pos.x } else { 0 };
+        self.goto_line(pos.y);
+        if !exact {
+            self.move_to_first_non_blank();
+        }
+    }
+
+    /// Feeds the keys recorded in `register` back through [`Editor::handle_key_event`]
+    pub fn replay_macro(&mut self, register: char, count: Option<usize>) {
+        if self.replaying_macros.contains(&register) {
+            return self.log(LogMessage::Error(format!("recursive macro @{register}")));
+        }
+        let Some(keys) = self.macros.get(&register).cloned() else {
+            return self.log(LogMessage::Warn(format!("register {register} is empty")));
+        };
+        // the keys inside the macro record their own changes for `.`
+        self.recording_change = None;
+        self.replaying_macros.push(register);
+        self.repeat(count, |ed| {
+            for key_event in &keys {
+                ed.handle_key_event(*key_event);
+            }
+        });
+        self.replaying_macros.pop();
+    }
 
-        let scroll_height = (self.cursor.y + 1).saturating_sub(adjusted_area.height);
+    /// `x` and `X`, deletes up to `count` grapheme clusters under or before the cursor without
+    /// leaving the line
+    pub fn delete_chars(&mut self, count: Option<usize>, forward: bool) {
+        let line = self.line_at_cursor();
+        let x = self.cursor.x.min(line.chars().count());
+        let n = count.unwrap_or(1);
+        let (from, to) = if forward {
+            (x, (0..n).fold(x, |x, _| next_grapheme(&line, x)))
+        } else {
+            ((0..n).fold(x, |x, _| prev_grapheme(&line, x)), x)
+        };
+        if from == to {
+            return;
+        }
+        let y = self.cursor.y;
+        self.register = Register {
+            text: self.delete_range(Position { x: from, y }, Position { x: to, y }),
+            linewise: false,
+        };
+        let line = self.line_at_cursor();
+        self.cursor.x = from.min(prev_grapheme(&line, line.chars().count()));
+    }
 
-        self.message_queue.to_paragraph().render(
-            Rect {
-                x: self.cursor.x,
-                y: self.cursor.y + 2,
-                width: 20,
-                height: 20,
+    /// `p` and `P`, pastes the register `count` times after or at the cursor and leaves the
+    /// cursor on the last pasted char. Whole lines go below or above the cursor line
+    pub fn paste(&mut self, count: Option<usize>, after: bool) {
+        let Some(last) = self.register.text.chars().last() else {
+            return;
+        };
+        let text = self.register.text.repeat(count.unwrap_or(1));
+        if self.register.linewise {
+            let y = self.cursor.y + usize::from(after);
+            if y >= self.line_count() && self.file_text.len_chars() > 0 && !self.ends_with_newline()
+            {
+                // the last line has no newline of its own to paste after
+                let end = Position {
+                    x: usize::MAX,
+                    y: y - 1,
+                };
+                self.insert_str(end, &format!("\n{}", text.trim_end_matches('\n')));
+            } else {
+                self.insert_str(Position { x: 0, y }, &text);
+            }
+            self.goto_line(y);
+            self.move_to_first_non_blank();
+            return;
+        }
+        let len = self.line_at_cursor().chars().count();
+        let pos = Position {
+            x: if after && len > 0 {
+                self.cursor.x.saturating_add(1).min(len)
+            } else {
+                self.cursor.x.min(len)
             },
-            buf,
-        );
+            y: self.cursor.y,
+        };
+        let start = self.get_byte_offset(pos);
+        self.insert_str(pos, &text);
+        self.cursor = self.position_from_byte_offset(start + text.len() - last.len_utf8());
+    }
 
-        Paragraph::new(text)
-            .left_aligned()
-            .block(block)
-            .scroll((scroll_height, 0))
-            .wrap(ratatui::widgets::Wrap { trim: false })
-            .render(adjusted_area, buf);
+    /// Applies `operator` over what `motion` covers. Deleted and yanked text goes to the register
+    pub fn apply_operator(&mut self, operator: Operator, motion: char, count: Option<usize>) {
+        if let Some(range) = self.motion_range(operator, motion, count) {
+            self.apply_operator_range(operator, range);
+        }
+    }
 
-        if self.mode == EditorMode::Command {
-            let command_block = Block::bordered()
-                .border_type(BorderType::Rounded)
-                .title_top("Command")
-                .style(Style::new().fg(Color::White).bg(theme.background.into()));
+    /// Applies `operator` over `range`, what a motion or text object covers
+    pub fn apply_operator_range(&mut self, operator: Operator, range: MotionRange) {
+        if let Operator::Indent | Operator::Dedent = operator {
+            let (top, bottom) = match range {
+                MotionRange::Lines(top, bottom) => (top, bottom),
+                MotionRange::Chars(start, end) => (start.y, end.y),
+            };
+            return self.shift_lines(top, bottom, operator == Operator::Indent);
+        }
+        let (start, end) = match range {
+            MotionRange::Chars(start, end) => (start, end),
+            // `cc` keeps the line itself around to type into
+            MotionRange::Lines(top, bottom)
+                if matches!(
+                    operator,
+                    Operator::Change | Operator::Lowercase | Operator::Uppercase
+                ) =>
+            {
+                (
+                    Position { x: 0, y: top },
+                    Position {
+                        x: self.line_at(bottom).chars().count(),
+                        y: bottom,
+                    },
+                )
+            }
+            MotionRange::Lines(top, bottom) => (
+                Position { x: 0, y: top },
+                Position {
+                    x: 0,
+                    y: bottom + 1,
+                },
+            ),
+        };
+        if let Operator::Lowercase | Operator::Uppercase = operator {
+            let change = if operator == Operator::Lowercase {
+                CaseChange::Lower
+            } else {
+                CaseChange::Upper
+            };
+            self.change_case(start, end, change);
+            self.cursor = start;
+            return;
+        }
+        let linewise = matches!(range, MotionRange::Lines(..));
+        let had_final_newline = self.ends_with_newline();
+        let mut text = if operator == Operator::Yank {
+            self.text_range(start, end)
+        } else {
+            self.delete_range(start, end)
+        };
+        // deleting the last lines shouldn't leave the newline before them dangling
+        if linewise && !had_final_newline && self.ends_with_newline() {
+            let last = self.file_text.len_chars() - 1;
+            self.file_text.remove(last..);
+        }
+        if linewise && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        if !text.is_empty() {
+            self.register = Register { text, linewise };
+        }
 
-            let percent_80: u16 = (f32::from(adjusted_area.width) * 0.8).round() as u16;
-            let percent_10: u16 = (f32::from(adjusted_area.width) * 0.1).round() as u16;
-            Paragraph::new(self.command.as_str())
-                .style(Style::new().fg(Color::White).bg(theme.background.into()))
-                .block(command_block)
-                .left_aligned()
-                .render(
-                    Rect::new(
-                        adjusted_area.x + percent_10,
-                        adjusted_area.y + 4,
-                        percent_80,
-                        3,
-                    ),
-                    buf,
+        match (operator, range) {
+            (Operator::Change, _) => {
+                self.cursor = start;
+                self.mode = EditorMode::Insert;
+            }
+            (Operator::Delete, MotionRange::Lines(top, _)) => {
+                self.goto_line(top);
+                self.move_to_first_non_blank();
+            }
+            (Operator::Yank, MotionRange::Lines(top, _)) => self.goto_line(top),
+            (_, MotionRange::Chars(..)) => self.cursor = start,
+            (
+                Operator::Indent | Operator::Dedent | Operator::Lowercase | Operator::Uppercase,
+                _,
+            ) => {}
+        }
+    }
+
+    /// Changes the case of the text from `start` up to but not including `end`, returns how
+    /// many chars it is afterwards
+    pub fn change_case(&mut self, start: Position, end: Position, change: CaseChange) -> usize {
+        let text = self.text_range(start, end);
+        let changed = change.apply(&text);
+        if changed != text {
+            self.replace_range(start, end, &changed);
+        }
+        changed.chars().count()
+    }
+
+    /// `~`, toggles the case of `count` chars from the cursor and moves past them
+    pub fn toggle_case(&mut self, count: Option<usize>) {
+        let len = self.line_at_cursor().chars().count();
+        if len == 0 {
+            return;
+        }
+        let n = count.unwrap_or(1).min(len.saturating_sub(self.cursor.x));
+        let end = Position {
+            x: self.cursor.x + n,
+            y: self.cursor.y,
+        };
+        let changed = self.change_case(self.cursor, end, CaseChange::Toggle);
+        let len = self.line_at_cursor().chars().count();
+        self.cursor.x = (self.cursor.x + changed).min(len - 1);
+    }
+
+    /// Changes the case of the visual selection and goes back to normal mode, a block
+    /// only changes the columns inside it
+    pub fn change_selection_case(&mut self, change: CaseChange) {
+        let (start, end) = self.block_bounds();
+        if self.mode == EditorMode::VisualBlock {
+            let cols = self.block_columns();
+            for y in start.y..=end.y {
+                let chars = self.chars_in_columns(y, cols.clone());
+                self.change_case(
+                    Position { x: chars.start, y },
+                    Position { x: chars.end, y },
+                    change,
                 );
+            }
+            self.cursor = Position {
+                x: self.chars_in_columns(start.y, cols).start,
+                y: start.y,
+            };
+        } else {
+            let (from, to) = if (self.anchor.y, self.anchor.x) < (self.cursor.y, self.cursor.x) {
+                (self.anchor, self.cursor)
+            } else {
+                (self.cursor, self.anchor)
+            };
+            self.change_case(from, Position { x: to.x + 1, ..to }, change);
+            self.cursor = from;
+        }
+        self.mode = EditorMode::Normal;
+    }
+
+    /// Indents or dedents lines `top..=bottom` by `shiftwidth`, dedenting takes off a tab or
+    /// up to `shiftwidth` spaces and never anything else. The cursor and the visual anchor stay
+    /// on the same char.
+    /// `shiftwidth` and not `tabwidth` like in vim: `tabwidth` is how wide a `\t` is drawn, so
+    /// a file with 8 column tabs can still be shifted by 4. Both start at 4
+    pub fn shift_lines(&mut self, top: usize, bottom: usize, indent: bool) {
+        let width = self.settings.shiftwidth.max(1);
+        let last = self.line_count().saturating_sub(1);
+        for y in top..=bottom.min(last) {
+            let line = self.line_at(y);
+            let delta = if indent {
+                if line.is_empty() {
+                    continue;
+                }
+                self.insert_str(Position { x: 0, y }, &" ".repeat(width));
+                width as isize
+            } else {
+                let n = if line.starts_with('\t') {
+                    1
+                } else {
+                    line.chars().take(width).take_while(|c| *c == ' ').count()
+                };
+                if n == 0 {
+                    continue;
+                }
+                self.delete_range(Position { x: 0, y }, Position { x: n, y });
+                -(n as isize)
+            };
+            if y == self.cursor.y {
+                self.cursor.x = self.cursor.x.saturating_add_signed(delta);
+            }
+            if y == self.anchor.y {
+                self.anchor.x = self.anchor.x.saturating_add_signed(delta);
+            }
+        }
+    }
+
+    /// `>` and `<` in visual mode, shifts every selected line and keeps the selection
+    pub fn shift_selection(&mut self, indent: bool) {
+        let (top, bottom) = if self.anchor.y <= self.cursor.y {
+            (self.anchor.y, self.cursor.y)
+        } else {
+            (self.cursor.y, self.anchor.y)
+        };
+        self.shift_lines(top, bottom, indent);
+    }
+
+    /// Works out what `motion` covers from the cursor without moving it. `gg`, `G`, `j`, `k`
+    /// and the operator key itself (`dd`) cover whole lines. `w` stops at the end of the line
+    /// and like vim `cw` changes to the end of the word instead of eating the whitespace after it
+    pub fn motion_range(
+        &mut self,
+        operator: Operator,
+        motion: char,
+        count: Option<usize>,
+    ) -> Option<MotionRange> {
+        let y = self.cursor.y;
+        let last = self.line_count().saturating_sub(1);
+        let n = count.unwrap_or(1).max(1);
+        let to_line = |target: usize| MotionRange::Lines(y.min(target), y.max(target));
+        match motion {
+            m if m == operator.key() => {
+                return Some(MotionRange::Lines(y, y.saturating_add(n - 1).min(last)));
+            }
+            'j' if y < last => return Some(to_line(y.saturating_add(n).min(last))),
+            'k' if y > 0 => return Some(to_line(y.saturating_sub(n))),
+            'j' | 'k' => return None,
+            'G' => return Some(to_line(count.map_or(last, |_| (n - 1).min(last)))),
+            'g' => return Some(to_line(count.map_or(0, |_| (n - 1).min(last)))),
+            _ => {}
+        }
+
+        let motion = match (operator, motion) {
+            (Operator::Change, 'w') => 'e',
+            (_, motion) => motion,
+        };
+        let start = self.cursor;
+        match motion {
+            'h' => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Left)),
+            'l' => self.repeat(count, |ed| ed.move_cursor(CursorDirection::Right)),
+            'w' => self.repeat(count, |ed| ed.move_to_start_of_next_pat(&WORD_PATTERN)),
+            'e' => self.repeat(count, |ed| ed.move_to_end_of_pat(&WORD_PATTERN)),
+            'b' => self.repeat(count, |ed| ed.move_to_start_of_prev_pat(&WORD_PATTERN)),
+            '0' => self.cursor.x = 0,
+            '^' => self.move_to_first_non_blank(),
+            // like vim `3D` and `d3$` also take the next two lines up to their ends
+            '$' => {
+                self.cursor.y = y.saturating_add(n - 1).min(last);
+                self.move_to_end_of_line();
+            }
+            _ => return None,
+        }
+        let mut end = self.cursor;
+        self.cursor = start;
+        if motion == 'w' && end.y != start.y {
+            end = Position {
+                x: self.line_at(start.y).chars().count(),
+                y: start.y,
+            };
+        }
+        let (from, mut to) = if (end.y, end.x) < (start.y, start.x) {
+            (end, start)
+        } else {
+            (start, end)
+        };
+        // `e` already lands just past the word, `$` lands on the last char
+        if motion == '$' {
+            to.x = self.line_at(to.y).chars().count();
+        }
+        Some(MotionRange::Chars(from, to))
+    }
+
+    /// Moves the cursor back onto the last line and the end of its line if it's past them
+    pub fn clamp_cursor(&mut self) {
+        self.goto_line(self.cursor.y);
+    }
+
+    /// Moves to line `y`, clamped to the last line, keeping the column if the line is long enough
+    pub fn goto_line(&mut self, y: usize) {
+        let last = self.line_count().saturating_sub(1);
+        self.cursor.y = y.min(last);
+        let len = self.line_at_cursor().chars().count();
+        self.cursor.x = self.cursor.x.min(len);
+    }
+
+    /// Finishes a normal mode command that was waiting for another key, `Esc` cancels it
+    pub fn handle_pending(&mut self, key_event: event::KeyEvent) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let c = match (key_event.code, pending) {
+            (KeyCode::Char(c), _) => c,
+            (KeyCode::Enter, Pending::Replace { .. }) => '\n',
+            _ => return,
+        };
+        match pending {
+            Pending::Find {
+                forward,
+                till,
+                count,
+            } => {
+                let find = CharFind {
+                    forward,
+                    till,
+                    target: c,
+                };
+                self.last_find = Some(find);
+                // like vim a count that overshoots the last match doesn't move at all
+                let start = self.cursor;
+                if !(0..count.unwrap_or(1)).all(|i| self.find_char(find, i > 0)) {
+                    self.cursor = start;
+                }
+            }
+            Pending::SetMark => _ = self.marks.insert(c, self.cursor),
+            Pending::JumpToMark { exact } => self.jump_to_mark(c, exact),
+            Pending::Record => {
+                if c.is_ascii_alphanumeric() {
+                    self.recording_macro = Some((c, Vec::new()));
+                }
+            }
+            Pending::Replay { count } => self.replay_macro(c, count),
+            Pending::G { .. } if self.read_only && matches!(c, 'u' | 'U') => self.refuse_edit(),
+            Pending::G { count } => {
+                self.pending = Operator::from_key(c)
+                    .filter(|operator| {
+                        matches!(operator, Operator::Lowercase | Operator::Uppercase)
+                    })
+                    .map(|operator| Pending::Operator {
+                        operator,
+                        count,
+                        g: false,
+                    });
+            }
+            Pending::Operator {
+                operator,
+                count,
+                g: false,
+            } if c == 'g' => {
+                self.pending = Some(Pending::Operator {
+                    operator,
+                    count,
+                    g: true,
+                });
+            }
+            Pending::Operator {
+                operator, g: false, ..
+            } if c == 'i' || c == 'a' => {
+                self.pending = Some(Pending::TextObject {
+                    operator,
+                    around: c == 'a',
+                });
+            }
+            Pending::Operator { g: true, .. } if c != 'g' => {}
+            Pending::Operator {
+                operator, count, ..
+            } => self.apply_operator(operator, c, count),
+            Pending::TextObject { operator, around } => {
+                if let Some(range) = self.text_object_range(c, around) {
+                    self.apply_operator_range(operator, range);
+                }
+            }
+            Pending::Replace { count } => {
+                let count = count.unwrap_or(1);
+                let available = self
+                    .line_at_cursor()
+                    .chars()
+                    .count()
+                    .saturating_sub(self.cursor.x);
+                // like vim nothing changes when there aren't enough chars left on the line
+                if count > available {
+                    return;
+                }
+                let end = Position {
+                    x: self.cursor.x + count,
+                    y: self.cursor.y,
+                };
+                // like vim replacing with Enter splits the line once no matter the count
+                if c == '\n' {
+                    self.replace_range(self.cursor, end, "\n");
+                    self.cursor = Position {
+                        x: 0,
+                        y: self.cursor.y + 1,
+                    };
+                    return;
+                }
+                self.replace_range(self.cursor, end, &c.to_string().repeat(count));
+                self.cursor.x = end.x - 1;
+            }
+        }
+    }
+    pub fn execute_command(&mut self) {
+        match self.command.clone().trim() {
+            // `:42` goes to line 42, a number past the end to the last line like `:$`
+            "$" => self.goto_line(usize::MAX),
+            line if !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit()) => {
+                let line: usize = line.parse().unwrap_or(usize::MAX);
+                self.goto_line(line.saturating_sub(1));
+            }
+            "q" if self.modified || self.hidden_buffers().any(|buffer| buffer.modified) => {
+                self.log(LogMessage::Error(
+                    "unsaved changes, use q! to override".into(),
+                ));
+            }
+            "q" | "q!" => self.exit(),
+            cmd if self.read_only && changes_buffer(cmd) => self.refuse_edit(),
+            "w" => _ = self.write(None),
+            "wq" | "x" => self.write_and_exit(None),
+            cmd if cmd.starts_with("w ") => _ = self.write(Some(cmd["w ".len()..].trim())),
+            cmd if cmd.starts_with("wq ") => self.write_and_exit(Some(cmd["wq ".len()..].trim())),
+            "e" => self.log(LogMessage::Error("aaaa".into())),
+            "noh" | "nohlsearch" => self.search_highlight = false,
+            "bn" | "bnext" => self.cycle_buffer(true),
+            "bp" | "bprevious" => self.cycle_buffer(false),
+            cmd if cmd.strip_prefix('b').is_some_and(|n| {
+                let n = n.trim_start();
+                !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+            }) =>
+            {
+                let n: usize = cmd[1..].trim_start().parse().unwrap_or(usize::MAX);
+                if (1..=self.buffers.len().max(1)).contains(&n) {
+                    self.switch_buffer(n - 1);
+                } else {
+                    self.log(LogMessage::Error(format!("no buffer {n}")));
+                }
+            }
+            "stats" => self.show_stats(),
+            "mkconfig" => self.write_config(false),
+            "mkconfig!" => self.write_config(true),
+            path if path.starts_with("theme ") => {
+                if let Err(e) = self.set_theme(Some(&path["theme ".len()..])) {
+                    self.log(LogMessage::Error(format!("{e:#}")));
+                }
+            }
+            opt if opt.starts_with("set ") => {
+                let result = self.set_options(&opt["set ".len()..]);
+                // options before a bad one are applied, so the syntax may still have changed
+                self.reload_syntax();
+                match result {
+                    Ok(()) => {}
+                    Err(e) if e.is::<UnknownOption>() => self.log(LogMessage::Warn(e.to_string())),
+                    Err(e) => self.log(LogMessage::Error(format!("{e:#}"))),
+                }
+            }
+            cmd if cmd.starts_with("sort") => self.sort(&cmd["sort".len()..]),
+            cmd if Substitution::is_candidate(cmd) => self.substitute(cmd),
+            _ => {}
+        }
+        self.end_command();
+    }
+    /// `:set`, `fileformat` belongs to the buffer so it's handled here and the rest of the
+    /// options by [`Settings::set`]
+    fn set_options(&mut self, args: &str) -> anyhow::Result<()> {
+        for arg in args.split_whitespace() {
+            match arg.split_once('=') {
+                Some(("fileformat" | "ff", name)) => {
+                    let Some(format) = FileFormat::from_name(name) else {
+                        bail!("fileformat must be unix or dos");
+                    };
+                    if format != self.file_format {
+                        self.file_format = format;
+                        self.modified = true;
+                    }
+                }
+                None if matches!(arg, "fileformat" | "ff") => {
+                    bail!("fileformat needs a value like fileformat=unix");
+                }
+                _ => self.settings.set(arg)?,
+            }
+        }
+        Ok(())
+    }
+    /// Searches for the typed pattern, an empty pattern repeats the last search
+    pub fn execute_search(&mut self) {
+        let pattern = std::mem::take(&mut self.command);
+        self.end_command();
+        if pattern.is_empty() {
+            self.search_next(true);
+        } else {
+            self.search(&pattern, true);
+        }
+    }
+    pub fn end_command(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.command = String::new();
+        self.command_lines = None;
+    }
+    /// Loads `theme/{name}.toml`, on failure the current theme stays
+    pub fn set_theme(&mut self, path: Option<impl ToString>) -> anyhow::Result<()> {
+        let path = path.map_or("default".to_string(), |v| v.to_string());
+        let full_path = ["theme", &path].join("/");
+        let full_path = [full_path, "toml".into()].join(".");
+        self.load_theme(&full_path)?;
+        self.theme_path = full_path;
+        Ok(())
+    }
+    fn load_theme(&mut self, full_path: &str) -> anyhow::Result<()> {
+        self.theme_mtime = theme_mtime(full_path);
+        let theme =
+            read_to_string(full_path).with_context(|| format!("failed to read {full_path}"))?;
+        self.theme =
+            toml::from_str(&theme).with_context(|| format!("invalid theme {full_path}"))?;
+        Ok(())
+    }
+    /// Re-reads the theme file if `:set autoreload` is on and it changed since it was last read
+    pub fn reload_theme_if_changed(&mut self) {
+        if !self.settings.autoreload || self.theme_path.is_empty() {
+            return;
+        }
+        if theme_mtime(&self.theme_path) == self.theme_mtime {
+            return;
+        }
+        let path = self.theme_path.clone();
+        if let Err(e) = self.load_theme(&path) {
+            self.log(LogMessage::Error(format!("{e:#}")));
+        }
+    }
+    pub fn substitute(&mut self, cmd: &str) {
+        let sub = match cmd.parse::<Substitution>() {
+            Ok(sub) => sub,
+            Err(e) => return self.log(LogMessage::Error(format!("substitute: {e}"))),
+        };
+        let lines = if sub.whole_file {
+            0..self.line_count()
+        } else {
+            self.cursor.y..self.cursor.y + 1
+        };
+        // only the lines that match get rewritten, nothing changes if any of them fails
+        let mut changed = Vec::new();
+        for y in lines {
+            let line = self.line_at(y);
+            match sub.apply(&line) {
+                Ok(Some(substituted)) => changed.push((y, line.chars().count(), substituted)),
+                Ok(None) => {}
+                Err(e) => return self.log(LogMessage::Error(format!("substitute: {e}"))),
+            }
+        }
+        let Some(&(last_line, _, _)) = changed.last() else {
+            return self.log(LogMessage::Warn("pattern not found".into()));
+        };
+        let mut count = 0;
+        for (y, len, substituted) in changed {
+            self.replace_range(
+                Position { x: 0, y },
+                Position { x: len, y },
+                &substituted.text,
+            );
+            count += substituted.count;
+        }
+        self.cursor = Position { x: 0, y: last_line };
+        self.log(LogMessage::Info(format!("{count} substitutions")));
+    }
+    /// `:sort` sorts the visual selection or the whole file, `!` reverses it and `n` sorts by
+    /// the first number in each line, lines without one go first
+    pub fn sort(&mut self, args: &str) {
+        let (reverse, args) = match args.strip_prefix('!') {
+            Some(args) => (true, args),
+            None => (false, args),
+        };
+        let numeric = match args.trim() {
+            "" => false,
+            "n" => true,
+            flag => return self.log(LogMessage::Error(format!("sort: unknown flag {flag}"))),
+        };
+        let text = self.file_text.to_string();
+        let body = text.strip_suffix('\n').unwrap_or(&text);
+        let mut lines: Vec<&str> = body.split('\n').collect();
+        let (top, bottom) = self.command_lines.unwrap_or((0, lines.len() - 1));
+        let range = top..(bottom + 1).min(lines.len());
+        if numeric {
+            lines[range.clone()].sort_by_key(|line| first_number(line));
+        } else {
+            lines[range.clone()].sort();
+        }
+        if reverse {
+            lines[range.clone()].reverse();
+        }
+        let mut sorted = lines.join("\n");
+        if body.len() < text.len() {
+            sorted.push('\n');
+        }
+        if sorted != text {
+            self.file_text = sorted.into();
+            self.mark_modified();
+        }
+        self.cursor = Position { x: 0, y: top };
+        self.log(LogMessage::Info(format!("{} lines sorted", range.len())));
+    }
+
+    /// `:stats`, counts the lines, words, chars and bytes of the buffer. Words are runs of
+    /// anything but whitespace
+    pub fn show_stats(&mut self) {
+        let text = Cow::from(&self.file_text);
+        let stats = format!(
+            "{} lines, {} words, {} chars, {} bytes",
+            self.line_count(),
+            text.split_whitespace().count(),
+            self.file_text.len_chars(),
+            self.file_text.len_bytes()
+        );
+        self.log(LogMessage::Info(stats));
+    }
+
+    /// What `--readonly` says to anything that would change or save the buffer
+    fn refuse_edit(&mut self) {
+        self.log(LogMessage::Warn("buffer is read-only".into()));
+    }
+
+    pub fn log(&mut self, msg: LogMessage) {
+        self.message = Some((msg, Instant::now()));
+    }
+
+    /// How long until the message goes away on its own, `None` for errors and no message
+    fn message_timeout(&self) -> Option<Duration> {
+        match &self.message {
+            Some((LogMessage::Info(_) | LogMessage::Warn(_), at)) => Some(
+                Duration::from_millis(self.settings.messagetimeout as u64)
+                    .saturating_sub(at.elapsed()),
+            ),
+            Some((LogMessage::Error(_), _)) | None => None,
+        }
+    }
+
+    /// Clears an info or warning message that has been up for `messagetimeout`
+    pub fn expire_message(&mut self) {
+        if self.message_timeout().is_some_and(|left| left.is_zero()) {
+            self.message = None;
+        }
+    }
+
+    /// Scrolls so the cursor line and column stay inside the text area, with `scrolloff` lines
+    /// around the cursor line unless that's the start or end of the file
+    pub fn update_scroll(&mut self) {
+        let height = self.text_height();
+        let margin = self.settings.scrolloff.min(height.saturating_sub(1) / 2);
+        let last = self.line_count().saturating_sub(1);
+        let top = self.cursor.y.saturating_sub(margin);
+        let bottom = self
+            .cursor
+            .y
+            .saturating_add(margin)
+            .min(last.max(self.cursor.y));
+        if top < self.scroll.y {
+            self.scroll.y = top;
+        } else if height > 0 && bottom >= self.scroll.y + height {
+            self.scroll.y = bottom - height + 1;
+        }
+
+        let width = self.text_width();
+        if self.settings.wrap {
+            self.scroll.x = 0;
+            // lines above the cursor can take several rows each, drop them off the top until
+            // the cursor's row is back on screen
+            while self.scroll.y < self.cursor.y && self.cursor_screen_offset().0 >= height {
+                self.scroll.y += 1;
+            }
+            return;
+        }
+        if width == 0 {
+            return;
+        }
+        let col = self.display_column();
+        if col < self.scroll.x {
+            self.scroll.x = col;
+        } else if col >= self.scroll.x + width {
+            self.scroll.x = col - width + 1;
+        }
+    }
+
+    /// Columns of text that fit between the gutter and the right border
+    pub fn text_width(&self) -> usize {
+        self.frame_area
+            .width
+            .saturating_sub(2 + self.gutter_width()) as usize
+    }
+
+    /// Display columns where each screen row of line `y` starts, only more than `0` when
+    /// `wrap` folds it
+    pub fn row_starts(&self, y: usize) -> Vec<usize> {
+        if !self.settings.wrap {
+            return vec![0];
+        }
+        let widths = expanded_widths(&self.line_at(y), self.settings.tabwidth, self.settings.list);
+        wrap_starts(widths, self.text_width())
+            .into_iter()
+            .map(|(_, col)| col)
+            .collect()
+    }
+
+    /// Screen row of the cursor inside its line and its column inside that row
+    pub fn cursor_row_in_line(&self) -> (usize, usize) {
+        let col = self.display_column();
+        let starts = self.row_starts(self.cursor.y);
+        let mut row = starts.iter().rposition(|start| *start <= col).unwrap_or(0);
+        let mut col = col - starts[row];
+        // past the end of a full last row, where insert mode appends, is the start of the next
+        let width = self.text_width().max(1);
+        if self.settings.wrap && col >= width {
+            row += col / width;
+            col %= width;
+        }
+        (row, col)
+    }
+
+    /// Where the cursor is on screen counted from the top left of the text area
+    pub fn cursor_screen_offset(&self) -> (usize, usize) {
+        if !self.settings.wrap {
+            return (
+                self.cursor.y.saturating_sub(self.scroll.y),
+                self.display_column().saturating_sub(self.scroll.x),
+            );
+        }
+        let above: usize = (self.scroll.y..self.cursor.y)
+            .map(|y| self.row_starts(y).len())
+            .sum();
+        let (row, col) = self.cursor_row_in_line();
+        (above.saturating_add(row), col)
+    }
+
+    /// Terminal cell of the cursor past the border and gutter, `None` while it's scrolled out
+    /// of the text area
+    pub fn cursor_screen_position(&self) -> Option<(u16, u16)> {
+        if self.cursor.y < self.scroll.y || self.display_column() < self.scroll.x {
+            return None;
+        }
+        let (row, col) = self.cursor_screen_offset();
+        if row >= self.text_height() || col >= self.text_width() {
+            return None;
+        }
+        Some((
+            self.frame_area.x + 1 + self.gutter_width() + clamp_u16(col),
+            self.frame_area.y + 1 + clamp_u16(row),
+        ))
+    }
+
+    /// Rows of text that fit inside the border, above the status line and the message row
+    pub fn text_height(&self) -> usize {
+        self.frame_area.height.saturating_sub(4) as usize
+    }
+
+    /// Bottom row: mode and file on the left, syntax, `line:col`, how far through the file the
+    /// cursor line is and the line count on the right
+    pub fn status_line(&self) -> (Line<'_>, Line<'_>) {
+        let mut left = format!(" {}  {}", self.mode.name(), self.file_path);
+        if self.modified {
+            left.push_str(" [+]");
+        }
+        if self.read_only {
+            left.push_str(" [RO]");
+        }
+        if self.buffers.len() > 1 {
+            left.push_str(&format!(
+                "  [{}/{}]",
+                self.buffer_index + 1,
+                self.buffers.len()
+            ));
+        }
+        if let Some((register, _)) = self.recording_macro {
+            left.push_str(&format!("  recording @{register}"));
+        }
+        let lang = match self.syntax {
+            Syntax::Plain => "plain",
+            Syntax::Regex(_) => self.syntax_lang(),
+        };
+        let lines = self.line_count().max(1);
+        let line = self.cursor.y + 1;
+        let right = format!(
+            "{lang}  {}  {line}:{}  {}%  {lines} lines ",
+            self.file_format.name(),
+            self.cursor.x + 1,
+            line * 100 / lines
+        );
+        (Line::from(left), Line::from(right))
+    }
+
+    /// `H`, `M` and `L`: moves to the top, middle or bottom visible line, a count for `H`
+    /// and `L` moves that many lines in from the edge
+    pub fn move_to_screen_line(&mut self, key: char, count: Option<usize>) {
+        let top = self.scroll.y;
+        let bottom = (top + self.text_height().max(1) - 1)
+            .min(self.line_count().saturating_sub(1))
+            .max(top);
+        let offset = count.unwrap_or(1).saturating_sub(1);
+        let y = match key {
+            'H' => top.saturating_add(offset).min(bottom),
+            'L' => bottom.saturating_sub(offset).max(top),
+            _ => (top + bottom) / 2,
+        };
+        self.goto_line(y);
+    }
+
+    /// Moves the cursor and the view by half a screen, `Ctrl-d` goes down and `Ctrl-u` up
+    pub fn scroll_half_page(&mut self, down: bool) {
+        let half = (self.text_height() / 2).max(1);
+        if down {
+            let lines = self.line_count();
+            let max_scroll = lines.saturating_sub(self.text_height());
+            self.scroll.y = self.scroll.y.saturating_add(half).min(max_scroll);
+            self.goto_line(self.cursor.y.saturating_add(half));
+        } else {
+            self.scroll.y = self.scroll.y.saturating_sub(half);
+            self.goto_line(self.cursor.y.saturating_sub(half));
+        }
+    }
+
+    /// Columns taken up by the line number gutter, including the padding after the numbers
+    pub fn gutter_width(&self) -> u16 {
+        if !self.settings.number && !self.settings.relativenumber {
+            return 0;
+        }
+        // sized for the last absolute number, which no relative distance can be longer than, so
+        // the text doesn't shift as the cursor moves
+        let digits = self.line_count().max(1).ilog10() + 1;
+        u16::try_from(digits.max(3)).unwrap_or_default() + 1
+    }
+
+    /// Number shown in the gutter for line `y`, with `relativenumber` it's relative to the
+    /// cursor except on the cursor line
+    pub fn line_number(&self, y: usize) -> usize {
+        if !self.settings.relativenumber || y == self.cursor.y {
+            y + 1
+        } else {
+            y.abs_diff(self.cursor.y)
+        }
+    }
+
+    /// Top left and bottom right corner of the visual block, both inclusive
+    pub fn block_bounds(&self) -> (Position, Position) {
+        (
+            Position {
+                x: self.anchor.x.min(self.cursor.x),
+                y: self.anchor.y.min(self.cursor.y),
+            },
+            Position {
+                x: self.anchor.x.max(self.cursor.x),
+                y: self.anchor.y.max(self.cursor.y),
+            },
+        )
+    }
+
+    /// Display columns covered by the visual block, the anchor and cursor can sit at
+    /// different char indices on lines with tabs or wide characters
+    pub fn block_columns(&self) -> Range<usize> {
+        let cells = |pos: Position| {
+            let col = self.column_at(pos);
+            col..self
+                .column_at(Position {
+                    x: pos.x + 1,
+                    ..pos
+                })
+                .max(col + 1)
+        };
+        let (anchor, cursor) = (cells(self.anchor), cells(self.cursor));
+        anchor.start.min(cursor.start)..anchor.end.max(cursor.end)
+    }
+
+    pub fn delete_block(&mut self) {
+        let (start, end) = self.block_bounds();
+        let cols = self.block_columns();
+        for y in (start.y..=end.y).rev() {
+            let chars = self.chars_in_columns(y, cols.clone());
+            if !chars.is_empty() {
+                self.delete_range(Position { x: chars.start, y }, Position { x: chars.end, y });
+            }
+        }
+        self.cursor = Position {
+            x: self.chars_in_columns(start.y, cols).start,
+            y: start.y,
+        };
+        self.mode = EditorMode::Normal;
+    }
+
+    pub fn start_block_insert(&mut self) {
+        let (start, end) = self.block_bounds();
+        let cols = self.block_columns();
+        self.block_insert = Some(BlockInsert {
+            col: cols.start,
+            top: start.y,
+            bottom: end.y,
+        });
+        self.cursor = Position {
+            x: self.chars_in_columns(start.y, cols).start,
+            y: start.y,
+        };
+        self.mode = EditorMode::Insert;
+    }
+
+    /// Repeats the text typed on the top line of a block insert on the remaining lines,
+    /// lines shorter than the block's left edge are skipped
+    pub fn finish_block_insert(&mut self) {
+        let Some(block) = self.block_insert.take() else {
+            return;
+        };
+        let cols = block.col..block.col + 1;
+        let start = self.chars_in_columns(block.top, cols.clone()).start;
+        if self.cursor.y != block.top || self.cursor.x <= start {
+            return;
+        }
+        let inserted: String = self
+            .line_at_cursor()
+            .chars()
+            .skip(start)
+            .take(self.cursor.x - start)
+            .collect();
+        for y in block.top + 1..=block.bottom {
+            if self.column_at(Position { x: usize::MAX, y }) < block.col {
+                continue;
+            }
+            let x = self.chars_in_columns(y, cols.clone()).start;
+            self.insert_str(Position { x, y }, &inserted);
         }
     }
 }
+
+#[derive(Debug)]
+pub enum LogMessage {
+    Error(String),
+    Warn(String),
+    Info(String),
+}
+
+impl LogMessage {
+    pub fn to_paragraph(&self) -> Paragraph<'_> {
+        match self {
+            LogMessage::Error(msg) => {
+                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::Red))
+            }
+
+            LogMessage::Warn(msg) => {
+                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::Yellow))
+            }
+            LogMessage::Info(msg) => {
+                Paragraph::new(msg.as_str()).style(Style::new().fg(Color::White))
+            }
+        }
+    }
+}
+
+pub struct State;
+
+impl StatefulWidget for &Editor {
+    type State = State;
+    fn render(
+        self,
+        area: ratatui::prelude::Rect,
+        buf: &mut ratatui::prelude::Buffer,
+        _state: &mut editor::State,
+    ) where
+        Self: Sized,
+    {
+        let theme = &self.theme;
+
+        let [area, status_area, message_area] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let (status_left, status_right) = self.status_line();
+        let status_style = Style::new()
+            .fg(theme.background.into())
+            .bg(theme.foreground().into());
+        Paragraph::new(status_left)
+            .style(status_style)
+            .render(status_area, buf);
+        Paragraph::new(status_right)
+            .right_aligned()
+            .render(status_area, buf);
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .style(
+                Style::new()
+                    .fg(theme.foreground().into())
+                    .bg(theme.background.into()),
+            )
+            .border_set(border::THICK);
+        // only the lines on screen get highlighted, which keeps a frame cheap in big files.
+        // lines are highlighted one at a time anyway so a block comment opened above the
+        // window was never coloured past its first line
+        let first = self.scroll.y.min(self.file_text.len_lines());
+        let last = (first + block.inner(area).height as usize).min(self.file_text.len_lines());
+        let visible = self
+            .file_text
+            .slice(self.file_text.line_to_char(first)..self.file_text.line_to_char(last));
+        let visible = Cow::from(visible);
+        let mut text = colour_text(&visible, theme, &self.syntax);
+
+        let search_style = Style::new()
+            .fg(theme.background.into())
+            .bg(theme.search_highlight().into());
+        for (y, range) in self.search_matches(first..last) {
+            if let Some(line) = text.lines.get_mut(y - first) {
+                patch_range_style(line, range, search_style);
+            }
+        }
+
+        if self.mode == EditorMode::VisualBlock {
+            let (start, end) = self.block_bounds();
+            let cols = self.block_columns();
+            let top = start.y.max(first);
+            for (y, line) in text
+                .lines
+                .iter_mut()
+                .enumerate()
+                .skip(top - first)
+                .take((end.y + 1).saturating_sub(top))
+            {
+                patch_range_style(
+                    line,
+                    self.chars_in_columns(first + y, cols.clone()),
+                    Style::new().add_modifier(Modifier::REVERSED),
+                );
+            }
+        }
+
+        for line in &mut text.lines {
+            if self.settings.list {
+                show_invisibles(line, Style::new().fg(theme.invisible().into()));
+            }
+            expand_tabs(
+                line,
+                self.settings.tabwidth,
+                self.settings.list.then_some('→'),
+            );
+        }
+
+        let adjusted_area = area;
+
+        buf.set_style(message_area, Style::new().bg(theme.background.into()));
+        if let Some((msg, _)) = &self.message {
+            msg.to_paragraph().render(message_area, buf);
+        }
+
+        let [gutter_area, text_area] =
+            Layout::horizontal([Constraint::Length(self.gutter_width()), Constraint::Fill(1)])
+                .areas(block.inner(adjusted_area));
+        block.render(adjusted_area, buf);
+
+        // with wrap a line can take up several rows, its number goes on the first one
+        let line_count = self.line_count().max(1);
+        let number_width = gutter_area.width.saturating_sub(1) as usize;
+        let mut lines = text.lines.into_iter();
+        let mut gutter_lines = Vec::new();
+        let mut rows = Vec::new();
+        let mut widths = Vec::new();
+        for y in (self.scroll.y..)
+            .take(text_area.height as usize)
+            .take_while(|y| *y < line_count)
+        {
+            let line = lines.next().unwrap_or_default();
+            gutter_lines.push(Line::from(format!(
+                "{:>number_width$} ",
+                self.line_number(y)
+            )));
+            if self.settings.wrap {
+                let folded = wrap_line(line, text_area.width as usize);
+                gutter_lines.extend(std::iter::repeat_n(Line::default(), folded.len() - 1));
+                rows.extend(folded);
+            } else {
+                widths.push(line.width());
+                rows.push(skip_columns(line, self.scroll.x));
+            }
+        }
+        Paragraph::new(gutter_lines)
+            .style(Style::new().fg(theme.line_number().into()))
+            .render(gutter_area, buf);
+
+        Paragraph::new(rows).left_aligned().render(text_area, buf);
+
+        // `<` and `>` on lines that go on past the left or right edge
+        if !self.settings.wrap {
+            let marker = Style::new().fg(theme.invisible().into());
+            let left = self.scroll.x;
+            for (y, width) in (text_area.y..text_area.bottom()).zip(widths) {
+                if left > 0 && width > 0 {
+                    buf.set_string(text_area.x, y, "<", marker);
+                }
+                if width > left + text_area.width as usize && text_area.width > 0 {
+                    buf.set_string(text_area.right() - 1, y, ">", marker);
+                }
+            }
+        }
+
+        // the guide and the cursor line and column only tint cells on the plain background so
+        // search matches stay visible on them
+        let plain_bg: Color = theme.background.into();
+        let mut tint = |rect: Rect, colour: Colour| {
+            for pos in rect.intersection(text_area).positions() {
+                let cell = &mut buf[pos];
+                if cell.bg == plain_bg || cell.bg == Color::Reset {
+                    cell.set_bg(colour.into());
+                }
+            }
+        };
+        if let Some(column) = self.settings.colorcolumn
+            && let Some(offset) = column
+                .checked_sub(1)
+                .and_then(|col| usize::from(col).checked_sub(self.scroll.x))
+        {
+            tint(
+                Rect {
+                    x: text_area.x.saturating_add(clamp_u16(offset)),
+                    width: 1,
+                    ..text_area
+                },
+                theme.colorcolumn(),
+            );
+        }
+        let (row, col) = self.cursor_screen_offset();
+        if self.settings.cursorline {
+            tint(
+                Rect {
+                    y: text_area.y + clamp_u16(row - self.cursor_row_in_line().0),
+                    height: clamp_u16(self.row_starts(self.cursor.y).len()),
+                    ..text_area
+                },
+                theme.cursorline(),
+            );
+        }
+        if self.settings.cursorcolumn {
+            let col = text_area.x.saturating_add(clamp_u16(col));
+            tint(
+                Rect {
+                    x: col,
+                    width: 1,
+                    ..text_area
+                },
+                theme.cursorline(),
+            );
+        }
+
+        if matches!(self.mode, EditorMode::Command | EditorMode::Search) {
+            let title = if self.mode == EditorMode::Search {
+                "Search"
+            } else {
+                "Command"
+            };
+            let command_block = Block::bordered()
+                .border_type(BorderType::Rounded)
+                .title_top(title)
+                .style(Style::new().fg(Color::White).bg(theme.background.into()));
+
+            let percent_80: u16 = (f32::from(adjusted_area.width) * 0.8).round() as u16;
+            let percent_10: u16 = (f32::from(adjusted_area.width) * 0.1).round() as u16;
+            Paragraph::new(self.command.as_str())
+                .style(Style::new().fg(Color::White).bg(theme.background.into()))
+                .block(command_block)
+                .left_aligned()
+                .render(
+                    Rect::new(
+                        adjusted_area.x + percent_10,
+                        adjusted_area.y + 4,
+                        percent_80,
+                        3,
+                    ),
+                    buf,
+                );
+        }
+    }
+}
+
+/// Narrows a position or length for ratatui, which counts cells in `u16`
+fn clamp_u16(n: usize) -> u16 {
+    u16::try_from(n).unwrap_or(u16::MAX)
+}
+
+fn theme_mtime(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Commands `--readonly` refuses, the ones that save the file or change its text
+fn changes_buffer(cmd: &str) -> bool {
+    matches!(cmd, "w" | "wq" | "x")
+        || cmd.starts_with("w ")
+        || cmd.starts_with("wq ")
+        || cmd.starts_with("sort")
+        || Substitution::is_candidate(cmd)
+}
+
+/// Closing char `autopairs` adds after `c`
+fn closing_pair(c: char) -> Option<char> {
+    Some(match c {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        '"' => '"',
+        '\'' => '\'',
+        _ => return None,
+    })
+}
+
+/// First whole number in `line` with its sign, what `:sort n` sorts by
+fn first_number(line: &str) -> Option<i64> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let digits = &line[start..];
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let number: i64 = digits[..end].parse().unwrap_or(i64::MAX);
+    Some(if line[..start].ends_with('-') {
+        -number
+    } else {
+        number
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEvent;
+
+    fn editor(text: &str) -> Editor {
+        Editor {
+            file_text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A fresh folder under the system temp dir that other tests and test runs don't share
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("sexditor_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn press(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            editor.handle_key_event(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn key(editor: &mut Editor, code: KeyCode) {
+        editor.handle_key_event(KeyEvent::from(code));
+    }
+
+    #[test]
+    fn test_past_u16_lines() {
+        let text: String = (0..100_000).map(|i| format!("  line {i}\n")).collect();
+        let mut editor = editor(&text);
+        let area = Rect::new(0, 0, 30, 10);
+        editor.frame_area = area;
+        press(&mut editor, "G");
+        assert_eq!(editor.cursor, Position { x: 2, y: 99_999 });
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 99_994);
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        (&editor).render(area, &mut buf, &mut State);
+        let row: String = (1..14).map(|x| buf[(x, 6)].symbol()).collect();
+        assert_eq!(row, "  line 99999 ");
+        assert_eq!(editor.cursor_screen_position(), Some((3, 6)));
+
+        press(&mut editor, "kdd");
+        assert_eq!(editor.cursor.y, 99_998);
+        assert_eq!(editor.line_at_cursor(), "  line 99999");
+        press(&mut editor, ":70000");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.line_at_cursor(), "  line 69999");
+        press(&mut editor, "gg");
+        assert_eq!(editor.cursor, Position { x: 2, y: 0 });
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 0);
+    }
+
+    /// Types 5000 chars into line `y` and backspaces over 1000 of them
+    fn type_into_line(editor: &mut Editor, y: usize) {
+        editor.cursor = Position { x: 5, y };
+        press(editor, "i");
+        press(editor, &"x".repeat(5_000));
+        for _ in 0..1_000 {
+            key(editor, KeyCode::Backspace);
+        }
+        key(editor, KeyCode::Esc);
+    }
+
+    /// `cargo test --release -- --ignored --nocapture bench` prints how long these take
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_typing_into_a_large_file() {
+        let mut editor = editor(&"some text on a line\n".repeat(100_000));
+        let start = Instant::now();
+        // walking every line before the cursor on each key used to take several seconds here
+        type_into_line(&mut editor, 50_000);
+        eprintln!("typing into line 50000 of 100000: {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_typing_into_a_large_file() {
+        let mut editor = editor(&"some text on a line\n".repeat(100_000));
+        type_into_line(&mut editor, 50_000);
+        assert_eq!(editor.line_count(), 100_000);
+        assert_eq!(
+            editor.line_at(50_000),
+            format!("some {}text on a line", "x".repeat(4_000))
+        );
+        assert_eq!(
+            editor.cursor,
+            Position {
+                x: 4_005,
+                y: 50_000
+            }
+        );
+    }
+
+    /// An editor on this file with the default theme and the rust syntax in a 120x50 terminal
+    fn rust_source_editor() -> Editor {
+        let mut editor = Editor {
+            file_path: "mod.rs".into(),
+            frame_area: Rect::new(0, 0, 120, 50),
+            ..editor(include_str!("mod.rs"))
+        };
+        editor
+            .load_theme(concat!(env!("CARGO_MANIFEST_DIR"), "/theme/default.toml"))
+            .unwrap();
+        editor.reload_syntax();
+        editor
+    }
+
+    fn render_frame(editor: &Editor) -> ratatui::buffer::Buffer {
+        let mut buf = ratatui::buffer::Buffer::empty(editor.frame_area);
+        editor.render(editor.frame_area, &mut buf, &mut State);
+        buf
+    }
+
+    #[test]
+    fn test_frames_reuse_the_loaded_theme_and_syntax() {
+        let dir = temp_dir("cached_theme_test");
+        let path = dir.join("theme.toml");
+        let theme = include_str!("../../theme/default.toml");
+        std::fs::write(&path, theme).unwrap();
+        let mut editor = rust_source_editor();
+        editor.theme_path = path.display().to_string();
+        editor.load_theme(&editor.theme_path.clone()).unwrap();
+        let first = render_frame(&editor);
+        assert!(matches!(editor.syntax, Syntax::Regex(_)));
+
+        // a theme changed on disk only shows up once it's loaded again
+        std::fs::write(&path, theme.replace("#d4d4d4", "#000000")).unwrap();
+        assert_eq!(render_frame(&editor), first);
+        editor.load_theme(&editor.theme_path.clone()).unwrap();
+        assert_ne!(render_frame(&editor), first);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_frames_with_and_without_reloading_theme_and_syntax() {
+        let theme = concat!(env!("CARGO_MANIFEST_DIR"), "/theme/default.toml");
+        let syntax = concat!(env!("CARGO_MANIFEST_DIR"), "/syntax/rs.toml");
+        let mut editor = rust_source_editor();
+        let frames = 200;
+        let lines = editor.line_count();
+        let time_frames = |editor: &mut Editor, reload: bool| {
+            let start = Instant::now();
+            for y in (0..lines).step_by(lines / frames).take(frames) {
+                if reload {
+                    // what every frame did before the theme and syntax were kept on `Editor`
+                    editor.load_theme(theme).unwrap();
+                    let syntax = toml::from_str(&read_to_string(syntax).unwrap()).unwrap();
+                    editor.syntax = Syntax::Regex(Box::new(syntax));
+                }
+                editor.cursor.y = y;
+                editor.update_scroll();
+                render_frame(editor);
+            }
+            start.elapsed() / frames as u32
+        };
+        let before = time_frames(&mut editor, true);
+        let after = time_frames(&mut editor, false);
+        eprintln!("per frame reading theme and syntax: {before:?}, loaded once: {after:?}");
+    }
+
+    /// Writes a 10 MB file to `path`, opens it, types 10000 chars at the end and saves it
+    fn append_to_large_file(path: &Path) {
+        let line = format!("{}\n", "0123456789".repeat(10));
+        std::fs::write(path, line.repeat(100_000)).unwrap();
+        let mut editor = editor("");
+        editor.open_new_file(Some(path.display().to_string()));
+        press(&mut editor, "GA");
+        for _ in 0..10_000 {
+            editor.type_char('x');
+        }
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_editing_the_end_of_a_10mb_file() {
+        let dir = temp_dir("large_file_bench");
+        let start = Instant::now();
+        // the text is a rope, so edits and line lookups near the end don't walk what's above
+        append_to_large_file(&dir.join("large.txt"));
+        eprintln!(
+            "opening, appending to and saving 10 MB: {:?}",
+            start.elapsed()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_editing_the_end_of_a_10mb_file() {
+        let dir = temp_dir("large_file_test");
+        let path = dir.join("large.txt");
+        append_to_large_file(&path);
+        let saved = read_to_string(&path).unwrap();
+        assert_eq!(saved.len(), 101 * 100_000 + 10_000);
+        assert!(saved.ends_with(&format!("9{}\n", "x".repeat(10_000))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_renders_only_the_visible_lines() {
+        let text: Vec<String> = (0..100).map(|i| format!("line {i}")).collect();
+        let mut editor = editor(&text.join("\n"));
+        let area = Rect::new(0, 0, 20, 8);
+        editor.frame_area = area;
+        editor.cursor.y = 60;
+        editor.update_scroll();
+        editor.search("line 5", true);
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        (&editor).render(area, &mut buf, &mut State);
+
+        let row = |y: u16| -> String { (1..9).map(|x| buf[(x, y)].symbol()).collect() };
+        // a line of `scrolloff` stays below the cursor, the most that fits in 4 rows
+        assert_eq!(editor.scroll.y, 58);
+        assert_eq!(row(1), "line 58 ");
+        assert_eq!(row(3), "line 60 ");
+        let highlight = editor.theme.search_highlight();
+        assert_eq!(buf[(1, 1)].bg, highlight.into());
+        assert_ne!(buf[(1, 3)].bg, highlight.into());
+    }
+
+    #[test]
+    fn test_status_line() {
+        let mut editor = Editor {
+            file_path: "main.rs".into(),
+            ..self::editor("fn main() {\n    todo!()\n}\n")
+        };
+        editor.reload_syntax();
+        press(&mut editor, "jwx");
+        editor.log(LogMessage::Error(
+            "an error far too long to fit on one row of the screen".into(),
+        ));
+        let area = Rect::new(0, 0, 51, 7);
+        editor.frame_area = area;
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        (&editor).render(area, &mut buf, &mut State);
+
+        let row = |y| -> String { (0..51).map(|x| buf[(x, y)].symbol()).collect() };
+        assert_eq!(
+            row(5),
+            " NORMAL  main.rs [+]   rs  unix  2:5  66%  3 lines "
+        );
+        assert_eq!(
+            row(6),
+            "an error far too long to fit on one row of the scre"
+        );
+        assert_eq!(buf[(0, 6)].fg, Color::Red);
+        assert_eq!(editor.text_height(), 3);
+
+        editor.modified = false;
+        editor.file_path = SCRATCH_BUFFER.into();
+        editor.reload_syntax();
+        editor.recording_macro = Some(('a', Vec::new()));
+        let (left, right) = editor.status_line();
+        assert_eq!(left.to_string(), " NORMAL  [scratch]  recording @a");
+        assert_eq!(right.to_string(), "plain  unix  2:5  66%  3 lines ");
+        press(&mut editor, "Gv");
+        assert_eq!(
+            editor.status_line().0.to_string(),
+            " VISUAL  [scratch]  recording @a"
+        );
+        assert!(editor.status_line().1.to_string().contains("  100%  "));
+    }
+
+    #[test]
+    fn test_line_number_gutter() {
+        let text: Vec<String> = (1..=1200).map(|i| format!("l{i}")).collect();
+        let mut editor = editor(&text.join("\n"));
+        assert_eq!(editor.gutter_width(), 0);
+        press(&mut editor, ":set nu");
+        key(&mut editor, KeyCode::Enter);
+        assert!(editor.settings.number);
+        assert_eq!(editor.gutter_width(), 5);
+        press(&mut editor, "998G");
+        assert_eq!(editor.line_number(1000), 1001);
+
+        let area = Rect::new(0, 0, 20, 8);
+        editor.frame_area = area;
+        editor.update_scroll();
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        (&editor).render(area, &mut buf, &mut State);
+        let row = |y: u16| -> String { (1..10).map(|x| buf[(x, y)].symbol()).collect() };
+        assert_eq!(row(1), " 996 l996");
+        assert_eq!(row(3), " 998 l998");
+        assert_eq!(buf[(2, 1)].fg, editor.theme.line_number().into());
+
+        editor.settings.relativenumber = true;
+        assert_eq!(editor.line_number(1000), 3);
+        press(&mut editor, ":set nonu nornu numbr");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.gutter_width(), 0);
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+        press(&mut editor, ":set nu=2");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+    }
+
+    #[test]
+    fn test_relative_line_numbers() {
+        let text: Vec<String> = (1..=1000).map(|i| format!("l{i}")).collect();
+        let mut editor = editor(&text.join("\n"));
+        press(&mut editor, ":set rnu");
+        key(&mut editor, KeyCode::Enter);
+        let area = Rect::new(0, 0, 20, 8);
+        editor.frame_area = area;
+        let render = |editor: &Editor| {
+            let mut buf = ratatui::buffer::Buffer::empty(area);
+            editor.render(area, &mut buf, &mut State);
+            (1..5)
+                .map(|y| (1..6).map(|x| buf[(x, y)].symbol()).collect())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(render(&editor), ["   1 ", "   1 ", "   2 ", "   3 "]);
+        press(&mut editor, "2j");
+        assert_eq!(render(&editor), ["   2 ", "   1 ", "   3 ", "   1 "]);
+        assert_eq!(editor.gutter_width(), 5);
+
+        press(&mut editor, ":set nornu");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.gutter_width(), 0);
+    }
+
+    #[test]
+    fn test_messages_time_out() {
+        let mut editor = editor("");
+        editor.log(LogMessage::Warn("pattern not found".into()));
+        editor.expire_message();
+        assert!(editor.message.is_some());
+        let logged = Instant::now() - Duration::from_millis(editor.settings.messagetimeout as u64);
+        if let Some((_, at)) = &mut editor.message {
+            *at = logged;
+        }
+        editor.expire_message();
+        assert!(editor.message.is_none());
+
+        press(&mut editor, ":set mt=0");
+        key(&mut editor, KeyCode::Enter);
+        editor.log(LogMessage::Info("1 substitutions".into()));
+        editor.expire_message();
+        assert!(editor.message.is_none());
+        editor.log(LogMessage::Error("no file name".into()));
+        editor.expire_message();
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+    }
+
+    #[test]
+    fn test_cursorline() {
+        let text: Vec<String> = (0..50).map(|i| format!("line {i}")).collect();
+        let mut editor = editor(&text.join("\n"));
+        press(&mut editor, ":set cul");
+        key(&mut editor, KeyCode::Enter);
+        let area = Rect::new(0, 0, 20, 8);
+        editor.frame_area = area;
+        editor.cursor.y = 30;
+        editor.update_scroll();
+        editor.search("line 3", false);
+        editor.cursor = Position { x: 0, y: 30 };
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        (&editor).render(area, &mut buf, &mut State);
+
+        let cursorline: Color = editor.theme.cursorline().into();
+        let row = clamp_u16(editor.cursor.y - editor.scroll.y) + 1;
+        assert_eq!(row, 3);
+        // the whole width of the text area, not just the text
+        assert_eq!(buf[(18, row)].bg, cursorline);
+        assert_eq!(buf[(7, row)].bg, cursorline);
+        assert_eq!(buf[(1, row)].bg, editor.theme.search_highlight().into());
+        assert_ne!(buf[(7, row - 1)].bg, cursorline);
+        assert_ne!(buf[(0, row)].bg, cursorline);
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut editor = Editor {
+            file_path: "lib.h".into(),
+            ..Default::default()
+        };
+        let config: Config = toml::from_str(
+            "theme = \"solarized\"\n[set]\nrelativenumber = true\n[filetypes]\nh = \"rs\"",
+        )
+        .unwrap();
+        editor.apply_config(config);
+        assert!(editor.settings.relativenumber);
+        assert_eq!(editor.theme_path, "theme/solarized.toml");
+        assert_eq!(editor.syntax_lang(), "rs");
+        editor.file_path = "lib.c".into();
+        assert_eq!(editor.syntax_lang(), "c");
+
+        editor.apply_config(toml::from_str("theme = \"nope\"").unwrap());
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert!(!editor.settings.relativenumber);
+    }
+
+    #[test]
+    fn test_colorcolumn() {
+        let mut editor = editor(&"x".repeat(40));
+        press(&mut editor, ":set nu cc=12");
+        key(&mut editor, KeyCode::Enter);
+        let area = Rect::new(0, 0, 20, 6);
+        editor.frame_area = area;
+        let render = |editor: &Editor| {
+            let mut buf = ratatui::buffer::Buffer::empty(area);
+            editor.render(area, &mut buf, &mut State);
+            let guide: Color = editor.theme.colorcolumn().into();
+            (0..20)
+                .filter(|&x| buf[(x, 1)].bg == guide && buf[(x, 2)].bg == guide)
+                .collect::<Vec<u16>>()
+        };
+        // one for the border and four for the gutter
+        assert_eq!(render(&editor), [1 + 4 + 11]);
+        editor.scroll.x = 4;
+        assert_eq!(render(&editor), [1 + 4 + 7]);
+        editor.scroll.x = 12;
+        assert_eq!(render(&editor), []);
+        editor.settings.colorcolumn = Some(0);
+        editor.scroll.x = 0;
+        assert_eq!(render(&editor), []);
+    }
+
+    #[test]
+    fn test_goto_first_and_last_line() {
+        let mut editor = editor("  first\nsecond line\n\t\tthird\n    last");
+        press(&mut editor, "jwG");
+        assert_eq!(editor.cursor, Position { x: 4, y: 3 });
+        press(&mut editor, "gg");
+        assert_eq!(editor.cursor, Position { x: 2, y: 0 });
+        press(&mut editor, "3G");
+        assert_eq!(editor.cursor, Position { x: 2, y: 2 });
+        press(&mut editor, "2gg");
+        assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+        press(&mut editor, "100G");
+        assert_eq!(editor.cursor, Position { x: 4, y: 3 });
+    }
+
+    #[test]
+    fn test_goto_line_command() {
+        let mut editor = editor("first line\nsecond\nthird line\nend");
+        let run = |editor: &mut Editor, cmd: &str| {
+            press(editor, &format!(":{cmd}"));
+            key(editor, KeyCode::Enter);
+        };
+        press(&mut editor, "$");
+        run(&mut editor, "2");
+        assert_eq!(editor.cursor, Position { x: 6, y: 1 });
+        assert_eq!(editor.mode, EditorMode::Normal);
+        run(&mut editor, " 3 ");
+        assert_eq!(editor.cursor.y, 2);
+        run(&mut editor, "$");
+        assert_eq!(editor.cursor, Position { x: 3, y: 3 });
+        run(&mut editor, "0");
+        assert_eq!(editor.cursor.y, 0);
+        run(&mut editor, "99999999999999999999999");
+        assert_eq!(editor.cursor.y, 3);
+    }
+
+    #[test]
+    fn test_horizontal_scroll_markers() {
+        let mut editor = editor(&format!("{}\nshort\n\n{}", "a".repeat(30), "b".repeat(22)));
+        let area = Rect::new(0, 0, 12, 8);
+        editor.frame_area = area;
+        let render = |editor: &Editor| {
+            let mut buf = ratatui::buffer::Buffer::empty(area);
+            editor.render(area, &mut buf, &mut State);
+            (1..5)
+                .map(|y| (1..11).map(|x| buf[(x, y)].symbol()).collect())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            render(&editor),
+            ["aaaaaaaaa>", "short     ", "          ", "bbbbbbbbb>"]
+        );
+        press(&mut editor, "$");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.x, 20);
+        assert_eq!(editor.cursor_screen_offset(), (0, 9));
+        assert_eq!(
+            render(&editor),
+            ["<aaaaaaaaa", "<         ", "          ", "<b        "]
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut editor = editor("one\n0123456789abcdefghij好x\n\tend");
+        let area = Rect::new(0, 0, 10, 9);
+        editor.frame_area = area;
+        press(&mut editor, ":set wrap nu");
+        key(&mut editor, KeyCode::Enter);
+        editor.scroll.x = 3;
+        editor.update_scroll();
+        assert_eq!(editor.scroll.x, 0);
+        let render = |editor: &Editor| {
+            let mut buf = ratatui::buffer::Buffer::empty(area);
+            editor.render(area, &mut buf, &mut State);
+            (1..6)
+                .map(|y| (1..9).map(|x| buf[(x, y)].symbol()).collect())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            render(&editor),
+            ["  1 one ", "  2 0123", "    4567", "    89ab", "    cdef"]
+        );
+        assert_eq!(editor.row_starts(1), [0, 4, 8, 12, 16, 20]);
+        // the wide char doesn't fit after `ghij` so it starts the next row
+        assert_eq!(editor.row_starts(1).len(), 6);
+
+        press(&mut editor, "j$");
+        assert_eq!(editor.cursor_row_in_line(), (5, 2));
+        assert_eq!(editor.cursor_screen_offset(), (6, 2));
+        // `g` is on the fifth row of line 2, so `one` scrolls off the top to keep it on screen
+        press(&mut editor, "016l");
+        assert_eq!(editor.cursor_screen_offset(), (5, 0));
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 1);
+        assert_eq!(editor.cursor_screen_offset(), (4, 0));
+        editor.scroll.y = 0;
+        press(&mut editor, "0llll");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 0);
+        assert_eq!(editor.cursor_screen_offset(), (2, 0));
+        press(&mut editor, "j");
+        editor.cursor.x = 1;
+        assert_eq!(editor.cursor_row_in_line(), (1, 0));
+    }
+
+    #[test]
+    fn test_scrolloff() {
+        let mut editor = editor(&"line\n".repeat(30));
+        editor.frame_area = Rect::new(0, 0, 20, 14);
+        press(&mut editor, "6j");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 0);
+        press(&mut editor, "j");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 1);
+        press(&mut editor, "G");
+        editor.update_scroll();
+        // no margin past the end of the file
+        assert_eq!(editor.scroll.y, 20);
+        press(&mut editor, "6k");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 20);
+        press(&mut editor, "k");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 19);
+
+        press(&mut editor, ":set so=0");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "9k");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 13);
+    }
+
+    #[test]
+    fn test_cursor_stays_on_screen_while_scrolling() {
+        let mut editor = editor(&"some line\n".repeat(50));
+        editor.frame_area = Rect::new(0, 0, 30, 10);
+        for _ in 0..60 {
+            press(&mut editor, "j");
+            editor.update_scroll();
+            let (_, y) = editor
+                .cursor_screen_position()
+                .map(|(x, y)| (x, y as usize))
+                .unwrap();
+            assert!((1..=editor.text_height()).contains(&y));
+            assert_eq!(y, editor.cursor.y - editor.scroll.y + 1);
+        }
+        assert_eq!(editor.cursor.y, 49);
+        press(&mut editor, "gg");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 0);
+        assert_eq!(editor.cursor_screen_position(), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_terminal_cursor_position() {
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(12, 8)).unwrap();
+        let mut editor = editor(&(0..20).map(|n| format!("line{n}\n")).collect::<String>());
+        editor.frame_area = terminal.get_frame().area();
+        press(&mut editor, ":set nu");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "9jll");
+        editor.update_scroll();
+        assert_eq!(editor.scroll.y, 7);
+        terminal.draw(|frame| editor.draw(frame)).unwrap();
+        // border and a 4 wide gutter to the left, 7 lines scrolled off the top
+        terminal.backend_mut().assert_cursor_position((7, 3));
+
+        // scrolled away from the cursor there's nowhere to show it
+        editor.scroll.y = 12;
+        assert_eq!(editor.cursor_screen_position(), None);
+        editor.scroll.y = 0;
+        assert_eq!(editor.cursor_screen_position(), None);
+        editor.scroll = Position { x: 3, y: 9 };
+        assert_eq!(editor.cursor_screen_position(), None);
+    }
+
+    #[test]
+    fn test_wide_chars_cursor_cell() {
+        use unicode_width::UnicodeWidthStr;
+
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(14, 6)).unwrap();
+        let mut editor = editor("ab中文cd漢字ef\n");
+        editor.frame_area = terminal.get_frame().area();
+        let mut draw = |editor: &mut Editor| {
+            editor.update_scroll();
+            terminal.draw(|frame| editor.draw(frame)).unwrap();
+            let backend = terminal.backend_mut();
+            let (x, y) = editor.cursor_screen_position().unwrap();
+            backend.assert_cursor_position((x, y));
+            // the cell after a wide char is covered by it
+            let mut row = String::new();
+            let mut col = 1;
+            while col < 13 {
+                let symbol = backend.buffer()[(col, y)].symbol();
+                row.push_str(symbol);
+                col += clamp_u16(symbol.width().max(1));
+            }
+            (backend.buffer()[(x, y)].symbol().to_string(), row)
+        };
+        // the two wide chars before `c` take two cells each
+        press(&mut editor, "4l");
+        assert_eq!(editor.cursor_screen_position(), Some((7, 1)));
+        assert_eq!(draw(&mut editor).0, "c");
+        press(&mut editor, "h");
+        assert_eq!(editor.cursor_screen_position(), Some((5, 1)));
+        assert_eq!(draw(&mut editor).0, "文");
+
+        // 14 columns don't fit in 12, `$` scrolls by display columns to keep `f` in view and the
+        // `<` marker covers half of `中`
+        press(&mut editor, "$");
+        assert_eq!(draw(&mut editor), ("f".into(), "< 文cd漢字ef".into()));
+        assert_eq!(editor.scroll.x, 2);
+        assert_eq!(editor.cursor_screen_position(), Some((12, 1)));
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "A");
+        // `中` is cut in half by the left edge, the rest of the line doesn't shift left
+        assert_eq!(draw(&mut editor), (" ".into(), "<文cd漢字ef ".into()));
+        assert_eq!(editor.scroll.x, 3);
+        assert_eq!(editor.cursor_screen_position(), Some((12, 1)));
+    }
+
+    #[test]
+    fn test_cursor_style() {
+        let mut editor = editor("one");
+        assert_eq!(editor.mode.cursor_style(), SetCursorStyle::BlinkingBlock);
+        press(&mut editor, "i");
+        assert_eq!(editor.mode.cursor_style(), SetCursorStyle::BlinkingBar);
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "R");
+        assert_eq!(
+            editor.mode.cursor_style(),
+            SetCursorStyle::BlinkingUnderScore
+        );
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "v");
+        assert_eq!(editor.mode.cursor_style(), SetCursorStyle::BlinkingBlock);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut editor = editor("héllo wörld\n\tfoo  bar\u{3000}好\n");
+        press(&mut editor, ":stats");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(
+            editor.message,
+            Some((LogMessage::Info(ref msg), _)) if msg == "2 lines, 5 words, 24 chars, 30 bytes"
+        ));
+
+        let mut editor = self::editor("");
+        press(&mut editor, "g");
+        editor.handle_key_event(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL));
+        assert!(matches!(
+            editor.message,
+            Some((LogMessage::Info(ref msg), _)) if msg == "0 lines, 0 words, 0 chars, 0 bytes"
+        ));
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut sorted = editor("pear\napple\nfig\n");
+        press(&mut sorted, "G:sort");
+        key(&mut sorted, KeyCode::Enter);
+        assert_eq!(sorted.file_text, "apple\nfig\npear\n");
+        assert_eq!(sorted.cursor, Position { x: 0, y: 0 });
+        assert!(sorted.modified);
+        assert!(matches!(
+            sorted.message,
+            Some((LogMessage::Info(ref msg), _)) if msg == "3 lines sorted"
+        ));
+
+        press(&mut sorted, ":sort!");
+        key(&mut sorted, KeyCode::Enter);
+        assert_eq!(sorted.file_text, "pear\nfig\napple\n");
+
+        let mut sorted = editor("x10\nx9\nnone\n-3\nb2\na2");
+        press(&mut sorted, ":sort n");
+        key(&mut sorted, KeyCode::Enter);
+        assert_eq!(sorted.file_text, "none\n-3\nb2\na2\nx9\nx10");
+
+        // only the selected lines move
+        let mut sorted = editor("d\nc\nb\na");
+        press(&mut sorted, "jvj:sort");
+        key(&mut sorted, KeyCode::Enter);
+        assert_eq!(sorted.file_text, "d\nb\nc\na");
+        assert_eq!(sorted.cursor, Position { x: 0, y: 1 });
+        assert_eq!(sorted.mode, EditorMode::Normal);
+        assert_eq!(sorted.command_lines, None);
+
+        press(&mut sorted, ":sort x");
+        key(&mut sorted, KeyCode::Enter);
+        assert_eq!(sorted.file_text, "d\nb\nc\na");
+    }
+
+    #[test]
+    fn test_keymap_bindings() {
+        let mut editor = editor("one\ntwo\nthree");
+        editor
+            .keymap
+            .merge("[normal]\nDown = \"cursor_down\"\nj = \"nop\"\n[insert]\nUp = \"cursor_up\"")
+            .unwrap();
+        key(&mut editor, KeyCode::Down);
+        key(&mut editor, KeyCode::Down);
+        press(&mut editor, "j");
+        assert_eq!(editor.cursor.y, 2);
+        press(&mut editor, "2k");
+        assert_eq!(editor.cursor.y, 0);
+
+        key(&mut editor, KeyCode::Down);
+        press(&mut editor, "i");
+        key(&mut editor, KeyCode::Up);
+        press(&mut editor, "x");
+        assert_eq!(editor.file_text, "xone\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_key_sequences() {
+        let mut editor = editor("one\ntwo\nthree\nfour");
+        press(&mut editor, "G3gg");
+        assert_eq!(editor.cursor.y, 2);
+        // a key that doesn't continue the sequence drops the `g` instead of waiting on it
+        press(&mut editor, "gjg");
+        assert_eq!(editor.cursor.y, 2);
+        assert_eq!(editor.pending, None);
+        assert_eq!(editor.pending_keys.len(), 1);
+        press(&mut editor, "U$");
+        assert_eq!(editor.file_text, "one\ntwo\nTHREE\nfour");
+
+        press(&mut editor, "] ");
+        assert_eq!(editor.file_text, "one\ntwo\nTHREE\n\nfour");
+        press(&mut editor, "2[ ");
+        assert_eq!(editor.file_text, "one\ntwo\n\n\nTHREE\n\nfour");
+        assert_eq!(editor.cursor.y, 4);
+        press(&mut editor, ".");
+        assert_eq!(editor.cursor.y, 6);
+        assert_eq!(editor.line_count(), 9);
+
+        press(&mut editor, "g");
+        assert_eq!(editor.pending_keys.len(), 1);
+        editor.time_out_pending_keys();
+        assert!(editor.pending_keys.is_empty());
+        assert_eq!(editor.pending, Some(Pending::G { count: None }));
+
+        editor
+            .keymap
+            .merge("[normal]\n\"g x\" = \"nop\"\n\"g g g\" = \"goto_line\"")
+            .unwrap();
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "gg");
+        editor.time_out_pending_keys();
+        assert_eq!(editor.cursor.y, 0);
+        press(&mut editor, "ggg");
+        assert_eq!(editor.cursor.y, 8);
+
+        editor.run_action(Action::GotoFirstLine, Some(2));
+        assert_eq!(editor.cursor.y, 1);
+    }
+
+    #[test]
+    fn test_toggle_case() {
+        let mut editor = editor("aBc straße");
+        press(&mut editor, "~");
+        assert_eq!(editor.file_text, "ABc straße");
+        assert_eq!(editor.cursor.x, 1);
+        press(&mut editor, "20~");
+        assert_eq!(editor.file_text, "AbC STRASSE");
+        assert_eq!(editor.cursor.x, 10);
+    }
+
+    #[test]
+    fn test_case_operators() {
+        let mut editor = editor("one two\nthree\nfour");
+        press(&mut editor, "wgUw");
+        assert_eq!(editor.file_text, "one TWO\nthree\nfour");
+        assert_eq!(editor.cursor, Position { x: 4, y: 0 });
+        press(&mut editor, "2gUU");
+        assert_eq!(editor.file_text, "ONE TWO\nTHREE\nfour");
+        press(&mut editor, "wgu$");
+        assert_eq!(editor.file_text, "ONE two\nTHREE\nfour");
+        press(&mut editor, "jguu");
+        assert_eq!(editor.file_text, "ONE two\nthree\nfour");
+        press(&mut editor, "jgUgg");
+        assert_eq!(editor.file_text, "ONE TWO\nTHREE\nFOUR");
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+        press(&mut editor, "Ggg");
+        assert_eq!(editor.cursor.y, 0);
+    }
+
+    #[test]
+    fn test_text_object_operators() {
+        let mut editor = editor("let s = \"foo bar\";\nf(a, (b))");
+        press(&mut editor, "ci\"x");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(editor.file_text, "let s = \"x\";\nf(a, (b))");
+        assert_eq!(editor.mode, EditorMode::Normal);
+        press(&mut editor, "0daw");
+        assert_eq!(editor.file_text, "s = \"x\";\nf(a, (b))");
+        press(&mut editor, "jfbdi(");
+        assert_eq!(editor.file_text, "s = \"x\";\nf(a, ())");
+        press(&mut editor, "0lya(");
+        assert_eq!(editor.register.text, "(a, ())");
+        press(&mut editor, "0di{");
+        assert_eq!(editor.file_text, "s = \"x\";\nf(a, ())");
+    }
+
+    #[test]
+    fn test_visual_case() {
+        let mut editor = editor("hello world\nfoo");
+        press(&mut editor, "lvjU");
+        assert_eq!(editor.file_text, "hELLO WORLD\nFOo");
+        assert_eq!(editor.mode, EditorMode::Normal);
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+        editor.handle_key_event(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        press(&mut editor, "lju");
+        assert_eq!(editor.file_text, "helLO WORLD\nFoo");
+    }
+
+    #[test]
+    fn test_visual_block_uses_display_columns() {
+        let mut editor = editor("ab\tc\n漢字x\nabcd");
+        editor.frame_area = Rect::new(0, 0, 20, 8);
+        press(&mut editor, "l");
+        editor.handle_key_event(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        editor.cursor = Position { x: 2, y: 2 };
+        assert_eq!(editor.block_columns(), 1..3);
+        let buf = render_frame(&editor);
+        let reversed = |y| {
+            (0..20)
+                .filter(|&x| buf[(x, y)].modifier.contains(Modifier::REVERSED))
+                .count()
+        };
+        // the whole tab is highlighted, wide characters carry the style on their first cell
+        assert_eq!((reversed(1), reversed(2), reversed(3)), (3, 2, 2));
+
+        press(&mut editor, "d");
+        assert_eq!(editor.file_text, "ac\nx\nad");
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+
+        let mut editor = self::editor("\tx\nabcdefg");
+        press(&mut editor, "l");
+        editor.handle_key_event(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::CONTROL));
+        editor.cursor = Position { x: 4, y: 1 };
+        press(&mut editor, "I-");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(editor.file_text, "\t-x\nabcd-efg");
+    }
+
+    #[test]
+    fn test_arrow_keys() {
+        let mut editor = editor("long line\nab\nx");
+        key(&mut editor, KeyCode::Right);
+        key(&mut editor, KeyCode::Down);
+        assert_eq!(editor.cursor, Position { x: 1, y: 1 });
+
+        press(&mut editor, "0i");
+        for _ in 0..3 {
+            key(&mut editor, KeyCode::Right);
+        }
+        assert_eq!(editor.cursor, Position { x: 0, y: 2 });
+        key(&mut editor, KeyCode::Up);
+        key(&mut editor, KeyCode::Up);
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+        for _ in 0..9 {
+            key(&mut editor, KeyCode::Right);
+        }
+        key(&mut editor, KeyCode::Down);
+        assert_eq!(editor.cursor, Position { x: 2, y: 1 });
+        press(&mut editor, "c");
+        key(&mut editor, KeyCode::Left);
+        press(&mut editor, "-");
+        assert_eq!(editor.file_text, "long line\nab-c\nx");
+        assert_eq!(editor.mode, EditorMode::Insert);
+
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "v");
+        key(&mut editor, KeyCode::Left);
+        press(&mut editor, "U");
+        assert_eq!(editor.file_text, "long line\nab-C\nx");
+    }
+
+    #[test]
+    fn test_half_page_scroll() {
+        let text: Vec<String> = (0..30).map(|i| i.to_string()).collect();
+        let mut editor = editor(&text.join("\n"));
+        editor.frame_area = Rect::new(0, 0, 40, 14);
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+
+        editor.handle_key_event(ctrl('d'));
+        assert_eq!((editor.cursor.y, editor.scroll.y), (5, 5));
+        for _ in 0..10 {
+            editor.handle_key_event(ctrl('d'));
+        }
+        assert_eq!((editor.cursor.y, editor.scroll.y), (29, 20));
+
+        editor.handle_key_event(ctrl('u'));
+        assert_eq!((editor.cursor.y, editor.scroll.y), (24, 15));
+        for _ in 0..10 {
+            editor.handle_key_event(ctrl('u'));
+        }
+        assert_eq!((editor.cursor.y, editor.scroll.y), (0, 0));
+    }
+
+    #[test]
+    fn test_screen_line_motions() {
+        let text: Vec<String> = (0..30).map(|i| "x".repeat(i % 4)).collect();
+        let mut editor = editor(&text.join("\n"));
+        editor.frame_area = Rect::new(0, 0, 40, 14);
+        editor.scroll.y = 8;
+        editor.cursor = Position { x: 3, y: 11 };
+
+        press(&mut editor, "H");
+        assert_eq!(editor.cursor, Position { x: 0, y: 8 });
+        press(&mut editor, "L");
+        assert_eq!(editor.cursor, Position { x: 0, y: 17 });
+        editor.cursor.x = 1;
+        press(&mut editor, "M");
+        assert_eq!(editor.cursor, Position { x: 0, y: 12 });
+        press(&mut editor, "3H");
+        assert_eq!(editor.cursor.y, 10);
+        press(&mut editor, "2L");
+        assert_eq!(editor.cursor.y, 16);
+
+        editor.scroll.y = 25;
+        press(&mut editor, "L");
+        assert_eq!(editor.cursor.y, 29);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut editor = editor("fn a() {\n    body\n\n}");
+        press(&mut editor, "J");
+        assert_eq!(editor.file_text, "fn a() { body\n\n}");
+        assert_eq!(editor.cursor, Position { x: 8, y: 0 });
+        press(&mut editor, "jJ");
+        assert_eq!(editor.file_text, "fn a() { body\n}");
+        press(&mut editor, "J");
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+        assert_eq!(editor.file_text, "fn a() { body\n}");
+
+        let mut editor = self::editor("ä\nb\n  c\nd");
+        press(&mut editor, "3J");
+        assert_eq!(editor.file_text, "ä b c\nd");
+        assert_eq!(editor.cursor, Position { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn test_autoindent() {
+        let mut editor = editor("\tfn a() {}");
+        press(&mut editor, "$i");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "\tfn a() {\n\t    }");
+        assert_eq!(editor.cursor, Position { x: 5, y: 1 });
+        press(&mut editor, "x");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "\tfn a() {\n\t    x\n\t    }");
+
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ":set noai");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "i");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "\tfn a() {\n\t    x\n\t    \n}");
+        assert_eq!(editor.cursor, Position { x: 0, y: 3 });
+
+        // trailing spaces after the brace still indent, with a tab when tabs aren't expanded
+        let mut editor = self::editor("\tif x {  ");
+        press(&mut editor, ":set noet");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "A");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "\tif x {  \n\t\t");
+        assert_eq!(editor.cursor, Position { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn test_autopairs() {
+        let mut editor = editor("");
+        press(&mut editor, "i(");
+        assert_eq!(editor.file_text, "(");
+        key(&mut editor, KeyCode::Esc);
+
+        let mut editor = self::editor("");
+        press(&mut editor, ":set ap");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "if(a[\"x");
+        assert_eq!(editor.file_text, "f(a[\"x\"])");
+        assert_eq!(editor.cursor.x, 6);
+        press(&mut editor, "\"])");
+        assert_eq!(editor.file_text, "f(a[\"x\"])");
+        assert_eq!(editor.cursor.x, 9);
+        press(&mut editor, " don't {");
+        assert_eq!(editor.file_text, "f(a[\"x\"]) don't {}");
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "f(a[\"x\"]) don't ");
+        press(&mut editor, "'");
+        assert_eq!(editor.file_text, "f(a[\"x\"]) don't ''");
+        assert_eq!(editor.cursor.x, 17);
+    }
+
+    #[test]
+    fn test_cursor_stays_on_the_last_line() {
+        // a trailing newline ends the last line rather than starting another
+        for (text, keys) in [("a\nb\nc", "x"), ("a\nb\nc\n", "dd")] {
+            let mut editor = editor(text);
+            press(&mut editor, "jjjjj");
+            assert_eq!(editor.cursor.y, 2);
+            press(&mut editor, keys);
+            assert_eq!(editor.file_text, "a\nb\n");
+            assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+            press(&mut editor, "jj");
+            assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+            assert_eq!(editor.line_at_cursor(), "b");
+        }
+        let mut editor = editor("one\ntwo\nthree");
+        press(&mut editor, "G$jdkj");
+        assert_eq!(editor.file_text, "one");
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+        press(&mut editor, "dd");
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_open_line() {
+        let mut editor = editor("one\n  two");
+        press(&mut editor, "Ox");
+        assert_eq!(editor.file_text, "x\none\n  two");
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+        assert_eq!(editor.mode, EditorMode::Insert);
+
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "GOa");
+        assert_eq!(editor.file_text, "x\none\n  a\n  two");
+        assert_eq!(editor.cursor, Position { x: 3, y: 2 });
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "Gob");
+        assert_eq!(editor.file_text, "x\none\n  a\n  two\n  b");
+        assert_eq!(editor.cursor, Position { x: 3, y: 4 });
+
+        let mut editor = self::editor("äöü\nz");
+        press(&mut editor, "oy");
+        assert_eq!(editor.file_text, "äöü\ny\nz");
+
+        let mut editor = self::editor("");
+        press(&mut editor, "O");
+        assert_eq!(editor.file_text, "\n");
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_append() {
+        let mut editor = editor("äöü\n");
+        press(&mut editor, "A!");
+        assert_eq!(editor.file_text, "äöü!\n");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "0a-");
+        assert_eq!(editor.file_text, "ä-öü!\n");
+
+        let mut editor = self::editor("\n");
+        press(&mut editor, "ax");
+        assert_eq!(editor.file_text, "x\n");
+    }
+
+    #[test]
+    fn test_insert_at_first_non_blank() {
+        let mut editor = editor("    foo\n   ");
+        press(&mut editor, "$I-");
+        assert_eq!(editor.file_text, "    -foo\n   ");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "jI!");
+        assert_eq!(editor.file_text, "    -foo\n   !");
+    }
+
+    #[test]
+    fn test_search_next() {
+        let mut editor = editor("ab ab\nxx\näb ab");
+        editor.search("ab", true);
+        assert_eq!(editor.cursor, Position { x: 3, y: 0 });
+        editor.search_next(true);
+        assert_eq!(editor.cursor, Position { x: 3, y: 2 });
+        editor.search_next(true);
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+        assert!(matches!(editor.message, Some((LogMessage::Info(_), _))));
+        editor.search_next(false);
+        assert_eq!(editor.cursor, Position { x: 3, y: 2 });
+        editor.search_next(false);
+        assert_eq!(editor.cursor, Position { x: 3, y: 0 });
+
+        editor.search("^$", false);
+        assert_eq!(editor.cursor, Position { x: 3, y: 0 });
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+        editor.search("b$", false);
+        assert_eq!(editor.cursor, Position { x: 4, y: 2 });
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut editor = editor("aa\naa\nbb\naa");
+        press(&mut editor, "j:s/a/x/");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "aa\nxa\nbb\naa");
+        press(&mut editor, r":%s/(a)(a)/\2-\1/g");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "a-a\nxa\nbb\na-a");
+        assert_eq!(editor.cursor, Position { x: 0, y: 3 });
+        assert!(matches!(
+            editor.message,
+            Some((LogMessage::Info(ref msg), _)) if msg == "2 substitutions"
+        ));
+
+        press(&mut editor, ":%s/q/x/");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+        assert_eq!(editor.file_text, "a-a\nxa\nbb\na-a");
+
+        // the line break of a CRLF line isn't part of what `$` sees
+        let mut editor = self::editor("one\r\ntwo\r\n");
+        press(&mut editor, ":%s/o$/0/");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "one\r\ntw0\r\n");
+    }
+
+    #[test]
+    fn test_substitute_chars() {
+        let mut editor = editor("abcdef");
+        press(&mut editor, "ls-");
+        assert_eq!(editor.file_text, "a-cdef");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "2s+");
+        assert_eq!(editor.file_text, "a-+ef");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "$s!");
+        assert_eq!(editor.file_text, "a-+e!");
+        assert_eq!(editor.mode, EditorMode::Insert);
+    }
+
+    #[test]
+    fn test_tab_in_insert_mode() {
+        let mut editor = editor("ab");
+        press(&mut editor, "li");
+        key(&mut editor, KeyCode::Tab);
+        assert_eq!(editor.file_text, "a   b");
+        assert_eq!(editor.cursor, Position { x: 4, y: 0 });
+
+        editor = self::editor("b");
+        press(&mut editor, "i");
+        key(&mut editor, KeyCode::Tab);
+        key(&mut editor, KeyCode::Tab);
+        assert_eq!(editor.file_text, "        b");
+        key(&mut editor, KeyCode::BackTab);
+        assert_eq!(editor.file_text, "    b");
+        assert_eq!(editor.cursor, Position { x: 4, y: 0 });
+
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ":set noet");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, "0i");
+        key(&mut editor, KeyCode::Tab);
+        assert_eq!(editor.file_text, "\t    b");
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+        assert_eq!(editor.display_column(), 4);
+    }
+
+    #[test]
+    fn test_replace_mode() {
+        let mut editor = editor("abc\nxyz");
+        press(&mut editor, "lR12345");
+        assert_eq!(editor.mode, EditorMode::Replace);
+        assert_eq!(editor.file_text, "a12345\nxyz");
+        key(&mut editor, KeyCode::Backspace);
+        key(&mut editor, KeyCode::Backspace);
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "a12\nxyz");
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "a1c\nxyz");
+        assert_eq!(editor.cursor, Position { x: 2, y: 0 });
+
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+        key(&mut editor, KeyCode::Backspace);
+        press(&mut editor, "Q");
+        assert_eq!(editor.file_text, "a1c\nQyz");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(editor.mode, EditorMode::Normal);
+
+        press(&mut editor, ".");
+        assert_eq!(editor.file_text, "a1c\nQQz");
+    }
+
+    #[test]
+    fn test_backspace_joins_lines() {
+        let mut editor = editor("ab");
+        press(&mut editor, "li");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "a\nb");
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "ab");
+        assert_eq!(editor.cursor, Position { x: 1, y: 0 });
+
+        key(&mut editor, KeyCode::Backspace);
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "b");
+        assert_eq!(editor.cursor, Position { x: 0, y: 0 });
+
+        // the cursor counts chars, not bytes, on both sides of the join
+        let mut editor = self::editor("äö\r\n好x\nü");
+        press(&mut editor, "ji");
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "äö好x\nü");
+        assert_eq!(editor.cursor, Position { x: 2, y: 0 });
+        press(&mut editor, "!");
+        assert_eq!(editor.file_text, "äö!好x\nü");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "j0i");
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "äö!好xü");
+        assert_eq!(editor.cursor, Position { x: 5, y: 0 });
+    }
+
+    #[test]
+    fn test_stdin_buffer_needs_a_path() {
+        let mut editor = Editor::from_stdin("piped\n".into());
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+
+        let dir = temp_dir("stdin_test");
+        let path = dir.join("stdin.txt");
+        press(&mut editor, &format!(":w {}", path.display()));
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&path).unwrap(), "piped\n");
+        assert_eq!(editor.file_path, path.display().to_string());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_line_endings_survive_save() {
+        let dir = temp_dir("line_endings_test");
+        let [dos, noeol] = ["dos.txt", "noeol.txt"].map(|name| dir.join(name));
+        std::fs::write(&dos, "one\r\ntwo\r\n").unwrap();
+        std::fs::write(&noeol, "one\ntwo").unwrap();
+        let paths = [&dos, &noeol].map(|path| path.display().to_string());
+        let mut editor = Editor::new(paths.to_vec());
+        assert_eq!(editor.file_text, "one\ntwo\n");
+        assert_eq!(editor.file_format, FileFormat::Dos);
+        assert!(editor.status_line().1.to_string().contains("  dos  "));
+
+        press(&mut editor, "ox");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&dos).unwrap(), "one\r\nx\r\ntwo\r\n");
+        press(&mut editor, ":set ff=unix");
+        key(&mut editor, KeyCode::Enter);
+        assert!(editor.modified);
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&dos).unwrap(), "one\nx\ntwo\n");
+
+        press(&mut editor, ":bn");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_format, FileFormat::Unix);
+        press(&mut editor, "Gox");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ":set ff=dos");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&noeol).unwrap(), "one\r\ntwo\r\nx");
+
+        press(&mut editor, ":set ff=mac");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert_eq!(editor.file_format, FileFormat::Dos);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_buffers() {
+        let dir = temp_dir("buffers_test");
+        let [a, b, c] = ["a.txt", "b.txt", "c.txt"].map(|name| dir.join(name));
+        std::fs::write(&a, "alpha\n").unwrap();
+        std::fs::write(&b, "beta\nbeta\n").unwrap();
+        let paths = [&a, &b, &c].map(|path| path.display().to_string());
+        let mut editor = Editor::new(paths.to_vec());
+        editor.frame_area = Rect::new(0, 0, 80, 10);
+        assert_eq!(editor.file_text, "alpha\n");
+        assert!(editor.status_line().0.to_string().ends_with("a.txt  [1/3]"));
+
+        press(&mut editor, "x:bn");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_path, paths[1]);
+        assert!(!editor.modified);
+        press(&mut editor, "j:bn");
+        key(&mut editor, KeyCode::Enter);
+        // a missing file opens empty
+        assert_eq!(editor.file_text, "");
+        assert!(editor.status_line().0.to_string().contains("[3/3]"));
+        press(&mut editor, ":bn");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "lpha\n");
+        assert!(editor.modified);
+
+        press(&mut editor, ":bp");
+        key(&mut editor, KeyCode::Enter);
+        press(&mut editor, ":bp");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "beta\nbeta\n");
+        assert_eq!(editor.cursor.y, 1);
+
+        // unsaved changes in a buffer that isn't shown still stop `:q`
+        press(&mut editor, ":q");
+        key(&mut editor, KeyCode::Enter);
+        assert!(!editor.exit);
+        press(&mut editor, ":b 4");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(
+            editor.message,
+            Some((LogMessage::Error(ref msg), _)) if msg == "no buffer 4"
+        ));
+        press(&mut editor, ":b1");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.buffer_index, 0);
+        press(&mut editor, ":w");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&a).unwrap(), "lpha\n");
+        press(&mut editor, ":q");
+        key(&mut editor, KeyCode::Enter);
+        assert!(editor.exit);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut editor = Editor {
+            read_only: true,
+            ..editor("one two\nthree\n")
+        };
+        for keys in [
+            "x", "dd", "dw", "p", "rX", "J", "o", "ihi", ">>", "guw", "~", "vjd", "vU",
+        ] {
+            press(&mut editor, keys);
+            key(&mut editor, KeyCode::Esc);
+            assert_eq!(editor.file_text, "one two\nthree\n", "{keys}");
+            assert_eq!(editor.mode, EditorMode::Normal, "{keys}");
+        }
+        key(&mut editor, KeyCode::Backspace);
+        for cmd in [":w", ":wq", ":s/one/1/", ":sort!"] {
+            press(&mut editor, cmd);
+            key(&mut editor, KeyCode::Enter);
+            assert_eq!(editor.file_text, "one two\nthree\n", "{cmd}");
+            assert!(matches!(
+                editor.message,
+                Some((LogMessage::Warn(ref msg), _)) if msg == "buffer is read-only"
+            ));
+        }
+        assert!(!editor.modified);
+        assert!(!editor.exit);
+        assert!(editor.status_line().0.to_string().ends_with(" [RO]"));
+
+        // looking around still works
+        press(&mut editor, "wyej/thr");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+        press(&mut editor, "P");
+        assert_eq!(editor.file_text, "one two\nthree\n");
+        press(&mut editor, ":q");
+        key(&mut editor, KeyCode::Enter);
+        assert!(editor.exit);
+    }
+
+    #[test]
+    fn test_write_to_new_path() {
+        let mut editor = Editor::new(Vec::new());
+        editor.file_text = "new\n".into();
+        press(&mut editor, ":wq");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert!(!editor.exit);
+
+        let dir = temp_dir("write_test");
+        let path = dir.join("nested/file.txt");
+        press(&mut editor, &format!(":wq {}", path.display()));
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(read_to_string(&path).unwrap(), "new\n");
+        assert!(editor.exit);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_save_is_logged() {
+        let dir = temp_dir("save_fail_test");
+        let mut editor = editor("text");
+        press(&mut editor, &format!(":wq {}", dir.display()));
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert!(!editor.exit);
+        std::fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn test_quit_with_unsaved_changes() {
+        let dir = temp_dir("modified_test");
+        let path = dir.join("modified.txt");
+        let mut editor = editor("text");
+        editor.file_path = path.display().to_string();
+        press(&mut editor, "dl");
+        assert!(editor.modified);
+        press(&mut editor, ":q");
+        key(&mut editor, KeyCode::Enter);
+        assert!(!editor.exit);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+
+        press(&mut editor, ":q!");
+        key(&mut editor, KeyCode::Enter);
+        assert!(editor.exit);
+
+        editor.exit = false;
+        press(&mut editor, ":x");
+        key(&mut editor, KeyCode::Enter);
+        assert!(!editor.modified);
+        assert!(editor.exit);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_syntax_is_cached_until_changed() {
+        let mut editor = editor("x = 1");
+        assert!(matches!(editor.syntax, Syntax::Plain));
+        press(&mut editor, ":set syntax=python");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.syntax, Syntax::Regex(_)));
+        press(&mut editor, ":set syntax=");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.syntax, Syntax::Plain));
+    }
+
+    #[test]
+    fn test_broken_theme_keeps_the_current_one() {
+        let mut editor = editor("");
+        let solarized = concat!(env!("CARGO_MANIFEST_DIR"), "/theme/solarized.toml");
+        editor.load_theme(solarized).unwrap();
+        editor.theme_path = solarized.into();
+        let theme = format!("{:?}", editor.theme);
+
+        press(&mut editor, ":theme does_not_exist");
+        key(&mut editor, KeyCode::Enter);
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+
+        let dir = temp_dir("broken_theme_test");
+        let broken = dir.join("broken.toml");
+        std::fs::write(&broken, "keyword = \"#ffffff\"\nident = ").unwrap();
+        let res = editor.load_theme(&broken.display().to_string());
+        assert!(format!("{:#}", res.unwrap_err()).contains("line 2"));
+        assert_eq!(format!("{:?}", editor.theme), theme);
+        assert_eq!(editor.theme_path, solarized);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_autoreload_theme() {
+        let dir = temp_dir("autoreload_theme_test");
+        let path = &dir.join("theme.toml");
+        let theme = include_str!("../../theme/default.toml");
+        std::fs::write(path, theme).unwrap();
+        let mut editor = editor("");
+        editor.theme_path = path.display().to_string();
+        editor.load_theme(&editor.theme_path.clone()).unwrap();
+
+        let touch = |text: &str, secs: u64| {
+            std::fs::write(path, text).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+        let before = format!("{:?}", editor.theme);
+        touch(&theme.replace("#d4d4d4", "#000000"), 1);
+        editor.reload_theme_if_changed();
+        assert_eq!(format!("{:?}", editor.theme), before);
+
+        press(&mut editor, ":set autoreload");
+        key(&mut editor, KeyCode::Enter);
+        editor.reload_theme_if_changed();
+        let reloaded = format!("{:?}", editor.theme);
+        assert_ne!(reloaded, before);
+
+        touch("keyword = ", 2);
+        editor.reload_theme_if_changed();
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert_eq!(format!("{:?}", editor.theme), reloaded);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut editor = editor("one\n  two three\nfour");
+        press(&mut editor, "jwwmaG`a");
+        assert_eq!(editor.cursor, Position { x: 6, y: 1 });
+        press(&mut editor, "gg'a");
+        assert_eq!(editor.cursor, Position { x: 2, y: 1 });
+
+        press(&mut editor, "`b");
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+
+        editor.file_text = "one\n  t".into();
+        press(&mut editor, "gg`a");
+        assert_eq!(editor.cursor, Position { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut editor = editor("a1\nb2\nc3\nd4");
+        press(&mut editor, "qwrXjq");
+        assert_eq!(editor.recording_macro, None);
+        assert_eq!(editor.file_text, "X1\nb2\nc3\nd4");
+        press(&mut editor, "2@w");
+        assert_eq!(editor.file_text, "X1\nX2\nX3\nd4");
+        assert_eq!(editor.cursor.y, 3);
+
+        press(&mut editor, "qel@eq");
+        press(&mut editor, "@e");
+        assert!(matches!(editor.message, Some((LogMessage::Error(_), _))));
+        assert_eq!(editor.cursor.x, 2);
+
+        press(&mut editor, "@z");
+        assert!(matches!(editor.message, Some((LogMessage::Warn(_), _))));
+    }
+
+    #[test]
+    fn test_count_prefix() {
+        let mut editor = editor("0123456789abcdef");
+        press(&mut editor, "10l");
+        assert_eq!(editor.cursor.x, 10);
+        press(&mut editor, "0");
+        assert_eq!(editor.cursor.x, 0);
+        press(&mut editor, "3l2");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "l");
+        assert_eq!(editor.cursor.x, 4);
+
+        press(&mut editor, "123456");
+        assert_eq!(editor.pending_count, Some(MAX_COUNT));
+        press(&mut editor, "z");
+        assert_eq!(editor.pending_count, None);
+    }
+
+    #[test]
+    fn test_replace_char() {
+        let mut editor = editor("aäb");
+        press(&mut editor, "lrx");
+        assert_eq!(editor.file_text, "axb");
+        press(&mut editor, "0rü");
+        assert_eq!(editor.file_text, "üxb");
+        assert_eq!(editor.cursor.x, 0);
+        press(&mut editor, "l2rz");
+        assert_eq!(editor.file_text, "üzz");
+        assert_eq!(editor.cursor.x, 2);
+        press(&mut editor, "04ry");
+        assert_eq!(editor.file_text, "üzz");
+        assert_eq!(editor.mode, EditorMode::Normal);
+
+        let mut editor = self::editor("\nx");
+        press(&mut editor, "rq");
+        assert_eq!(editor.file_text, "\nx");
+
+        let mut editor = self::editor("ab cd");
+        press(&mut editor, "l2r");
+        key(&mut editor, KeyCode::Enter);
+        assert_eq!(editor.file_text, "a\ncd");
+        assert_eq!(editor.cursor, Position { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn test_dot_repeat() {
+        let mut editor = editor("abcdef\nxyz");
+        press(&mut editor, "2dl.");
+        assert_eq!(editor.file_text, "ef\nxyz");
+        press(&mut editor, "2.");
+        assert_eq!(editor.file_text, "\nxyz");
+
+        press(&mut editor, "jrQl.");
+        assert_eq!(editor.file_text, "\nQQz");
+
+        let mut editor = self::editor("a\nb");
+        press(&mut editor, "ifoo");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "j0.");
+        assert_eq!(editor.file_text, "fooa\nfoob");
+
+        press(&mut editor, "o-");
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, "kk.");
+        assert_eq!(editor.file_text, "fooa\n-\nfoob\n-");
+    }
+
+    #[test]
+    fn test_delete_graphemes() {
+        let text = "ae\u{301}🇩🇪👨\u{200d}👩\u{200d}👧b";
+        let mut editor = editor(text);
+        press(&mut editor, "A");
+        key(&mut editor, KeyCode::Backspace);
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "ae\u{301}🇩🇪");
+        assert_eq!(editor.cursor.x, 5);
+        key(&mut editor, KeyCode::Backspace);
+        assert_eq!(editor.file_text, "ae\u{301}");
+        assert_eq!(editor.cursor.x, 3);
+
+        let mut editor = self::editor(text);
+        press(&mut editor, "lx");
+        assert_eq!(editor.file_text, "a🇩🇪👨\u{200d}👩\u{200d}👧b");
+        assert_eq!(editor.register.text, "e\u{301}");
+        assert_eq!(editor.cursor.x, 1);
+        press(&mut editor, "$X");
+        assert_eq!(editor.file_text, "a🇩🇪b");
+        assert_eq!(editor.cursor.x, 3);
+        press(&mut editor, "2X");
+        assert_eq!(editor.file_text, "b");
+        assert_eq!(editor.cursor.x, 0);
+
+        // the last cluster going leaves the cursor on the start of the one before
+        let mut editor = self::editor(text);
+        press(&mut editor, "$hx");
+        assert_eq!(editor.file_text, "ae\u{301}🇩🇪b");
+        press(&mut editor, "h2x");
+        assert_eq!(editor.file_text, "ae\u{301}");
+        assert_eq!(editor.cursor.x, 1);
+    }
+
+    #[test]
+    fn test_delete_chars_and_paste() {
+        let mut editor = editor("abcdé");
+        press(&mut editor, "x");
+        assert_eq!(editor.file_text, "bcdé");
+        press(&mut editor, "$x");
+        assert_eq!(editor.file_text, "bcd");
+        assert_eq!(editor.cursor.x, 2);
+        press(&mut editor, "P");
+        assert_eq!(editor.file_text, "bcéd");
+        assert_eq!(editor.cursor.x, 2);
+
+        press(&mut editor, "$2X");
+        assert_eq!(editor.file_text, "bd");
+        assert_eq!(editor.register.text, "cé");
+        assert_eq!(editor.cursor.x, 1);
+        press(&mut editor, "0p");
+        assert_eq!(editor.file_text, "bcéd");
+        assert_eq!(editor.cursor.x, 2);
+
+        press(&mut editor, "09x");
+        assert_eq!(editor.file_text, "");
+        press(&mut editor, "xX2p");
+        assert_eq!(editor.file_text, "bcédbcéd");
+    }
+
+    #[test]
+    fn test_linewise_operators() {
+        let mut editor = editor("a\n  b\nc");
+        press(&mut editor, "jdd");
+        assert_eq!(editor.file_text, "a\nc");
+        assert_eq!(
+            editor.register,
+            Register {
+                text: "  b\n".into(),
+                linewise: true
+            }
+        );
+        press(&mut editor, "p");
+        assert_eq!(editor.file_text, "a\nc\n  b");
+        assert_eq!(editor.cursor, Position { x: 2, y: 2 });
+        press(&mut editor, "ggP");
+        assert_eq!(editor.file_text, "  b\na\nc\n  b");
+
+        press(&mut editor, "jdG");
+        assert_eq!(editor.file_text, "  b");
+        assert_eq!(editor.register.text, "a\nc\n  b\n");
+        press(&mut editor, "pdgg");
+        assert_eq!(editor.file_text, "c\n  b");
+
+        press(&mut editor, "yjG");
+        assert_eq!(editor.file_text, "c\n  b");
+        assert_eq!(editor.register.text, "c\n  b\n");
+        assert_eq!(editor.cursor.y, 1);
+
+        press(&mut editor, "kcc");
+        assert_eq!(editor.file_text, "\n  b");
+        assert_eq!(editor.mode, EditorMode::Insert);
+    }
+
+    #[test]
+    fn test_delete_and_change_to_end_of_line() {
+        let mut editor = editor("one two\n\nthree");
+        press(&mut editor, "wD");
+        assert_eq!(editor.file_text, "one \n\nthree");
+        assert_eq!(editor.register.text, "two");
+        assert_eq!(editor.cursor.x, 3);
+
+        press(&mut editor, "jD");
+        assert_eq!(editor.file_text, "one \n\nthree");
+        assert_eq!(editor.register.text, "two");
+
+        press(&mut editor, "jlCee");
+        assert_eq!(editor.file_text, "one \n\ntee");
+        assert_eq!(editor.register.text, "hree");
+        assert_eq!(editor.mode, EditorMode::Insert);
+
+        // a count takes the rest of the line and whole lines after it
+        let mut editor = self::editor("one two\nthree\nfour\nfive");
+        press(&mut editor, "w3D");
+        assert_eq!(editor.file_text, "one \nfive");
+        assert_eq!(editor.register.text, "two\nthree\nfour");
+        press(&mut editor, "9Cx");
+        assert_eq!(editor.file_text, "onex");
+        assert_eq!(editor.mode, EditorMode::Insert);
+
+        // the last grapheme goes whole
+        let mut editor = self::editor("ae\u{301}");
+        press(&mut editor, "lD");
+        assert_eq!(editor.file_text, "a");
+    }
+
+    #[test]
+    fn test_indent_and_dedent() {
+        let mut editor = editor("a\n\n\tb\n  c d");
+        press(&mut editor, ">>");
+        assert_eq!(editor.file_text, "    a\n\n\tb\n  c d");
+        assert_eq!(editor.cursor.x, 4);
+        press(&mut editor, "3>>");
+        assert_eq!(editor.file_text, "        a\n\n    \tb\n  c d");
+
+        press(&mut editor, "G$<<");
+        assert_eq!(editor.file_text, "        a\n\n    \tb\nc d");
+        assert_eq!(editor.cursor.x, 2);
+        press(&mut editor, "<<");
+        assert_eq!(editor.file_text, "        a\n\n    \tb\nc d");
+
+        press(&mut editor, "kv<");
+        assert_eq!(editor.file_text, "        a\n\n\tb\nc d");
+        assert_eq!(editor.mode, EditorMode::Visual);
+        press(&mut editor, "kk<");
+        assert_eq!(editor.file_text, "    a\n\nb\nc d");
+
+        editor.settings.shiftwidth = 2;
+        key(&mut editor, KeyCode::Esc);
+        press(&mut editor, ">>");
+        assert_eq!(editor.file_text, "      a\n\nb\nc d");
+
+        // both ends of the selection follow their text
+        let mut editor = self::editor("one\ntwo three");
+        press(&mut editor, "lvjll>");
+        assert_eq!(editor.file_text, "    one\n    two three");
+        assert_eq!(editor.anchor, Position { x: 5, y: 0 });
+        assert_eq!(editor.cursor, Position { x: 7, y: 1 });
+        press(&mut editor, "<");
+        assert_eq!(editor.anchor, Position { x: 1, y: 0 });
+        assert_eq!(editor.cursor, Position { x: 3, y: 1 });
+    }
+
+    #[test]
+    fn test_yank() {
+        let mut editor = editor("one two");
+        press(&mut editor, "wy$");
+        assert_eq!(editor.register.text, "two");
+        assert_eq!(editor.cursor.x, 4);
+        press(&mut editor, "0yw$p");
+        assert_eq!(editor.file_text, "one twoone ");
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut editor = editor("foo bar.baz\nnext");
+        press(&mut editor, "dw");
+        assert_eq!(editor.file_text, "bar.baz\nnext");
+        press(&mut editor, "2dw");
+        assert_eq!(editor.file_text, "baz\nnext");
+        press(&mut editor, "dw");
+        assert_eq!(editor.file_text, "\nnext");
+
+        let mut editor = self::editor("one two three");
+        press(&mut editor, "wd$");
+        assert_eq!(editor.file_text, "one ");
+        press(&mut editor, "0cwzero");
+        key(&mut editor, KeyCode::Esc);
+        assert_eq!(editor.file_text, "zero ");
+        press(&mut editor, "0c$x");
+        assert_eq!(editor.file_text, "x");
+        assert_eq!(editor.mode, EditorMode::Insert);
+    }
+
+    #[test]
+    fn test_find_motions_with_repeat() {
+        let mut editor = editor("ä, ö, ü, ß");
+        press(&mut editor, "f,");
+        assert_eq!(editor.cursor.x, 1);
+        press(&mut editor, ";");
+        assert_eq!(editor.cursor.x, 4);
+        press(&mut editor, ",");
+        assert_eq!(editor.cursor.x, 1);
+        press(&mut editor, "0t,");
+        assert_eq!(editor.cursor.x, 0);
+        press(&mut editor, ";");
+        assert_eq!(editor.cursor.x, 3);
+        press(&mut editor, "$F ");
+        assert_eq!(editor.cursor.x, 8);
+        press(&mut editor, "T,");
+        assert_eq!(editor.cursor.x, 8);
+    }
+
+    #[test]
+    fn test_find_motion_count() {
+        let mut editor = editor("a.b.c.d");
+        press(&mut editor, "3f.");
+        assert_eq!(editor.cursor.x, 5);
+        press(&mut editor, "04f.");
+        assert_eq!(editor.cursor.x, 0);
+        press(&mut editor, "2t.");
+        assert_eq!(editor.cursor.x, 2);
+    }
+}
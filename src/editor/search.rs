@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use fancy_regex::Regex;
+
+use crate::editor::{Editor, LogMessage, Position, cursor_actions::CursorAction};
+
+pub trait SearchAction {
+    fn search(&mut self, pattern: &str, forward: bool);
+    fn search_next(&mut self, forward: bool);
+    fn search_matches(&self, lines: Range<usize>) -> Vec<(usize, Range<usize>)>;
+}
+
+impl SearchAction for Editor {
+    /// Moves the cursor to the next match of `pattern` after (or before) the cursor,
+    /// wrapping around the file. `^` and `$` match at line boundaries
+    fn search(&mut self, pattern: &str, forward: bool) {
+        let regex = match Regex::new(&format!("(?m){pattern}")) {
+            Ok(regex) => regex,
+            Err(e) => return self.log(LogMessage::Error(format!("invalid pattern: {e}"))),
+        };
+        self.last_search = Some(regex);
+        self.search_next(forward);
+    }
+
+    /// Repeats the last search, `forward = false` searches in the opposite direction.
+    /// Matches don't span lines, the search stops at the first hit
+    fn search_next(&mut self, forward: bool) {
+        let Some(regex) = &self.last_search else {
+            return self.log(LogMessage::Error("no previous search pattern".into()));
+        };
+        self.search_highlight = true;
+        let Position { x, y } = self.cursor;
+        let line = self.line_at(y);
+        let first = |y: usize| {
+            let line = self.line_at(y);
+            let start = regex.find(&line).ok().flatten()?.start();
+            Some(Position {
+                x: line[..start].chars().count(),
+                y,
+            })
+        };
+        let last_before = |y: usize, end: usize| {
+            let line = self.line_at(y);
+            let start = regex
+                .find_iter(&line)
+                .filter_map(Result::ok)
+                .map(|mat| mat.start())
+                .take_while(|start| *start < end)
+                .last()?;
+            Some(Position {
+                x: line[..start].chars().count(),
+                y,
+            })
+        };
+
+        let cursor_byte = line.char_indices().nth(x).map_or(line.len(), |(i, _)| i);
+        let found = if forward {
+            let after = line[cursor_byte..]
+                .chars()
+                .next()
+                .map_or(cursor_byte, |c| cursor_byte + c.len_utf8());
+            regex
+                .find_from_pos(&line, after)
+                .ok()
+                .flatten()
+                .filter(|mat| mat.start() > cursor_byte)
+                .map(|mat| Position {
+                    x: line[..mat.start()].chars().count(),
+                    y,
+                })
+                .or_else(|| (y + 1..self.line_count()).find_map(first))
+                .map(|pos| (pos, false))
+                .or_else(|| (0..=y).find_map(first).map(|pos| (pos, true)))
+        } else {
+            last_before(y, cursor_byte)
+                .or_else(|| (0..y).rev().find_map(|y| last_before(y, usize::MAX)))
+                .map(|pos| (pos, false))
+                .or_else(|| {
+                    (y..self.line_count())
+                        .rev()
+                        .find_map(|y| last_before(y, usize::MAX))
+                        .map(|pos| (pos, true))
+                })
+        };
+
+        let Some((pos, wrapped)) = found else {
+            return self.log(LogMessage::Warn("pattern not found".into()));
+        };
+        self.cursor = pos;
+        if wrapped {
+            let msg = if forward {
+                "search hit BOTTOM, continuing at TOP"
+            } else {
+                "search hit TOP, continuing at BOTTOM"
+            };
+            self.log(LogMessage::Info(msg.into()));
+        }
+    }
+
+    /// Line index and char range of every match of the last search on `lines` while
+    /// highlighting is on
+    fn search_matches(&self, lines: Range<usize>) -> Vec<(usize, Range<usize>)> {
+        let Some(regex) = self.last_search.as_ref().filter(|_| self.search_highlight) else {
+            return Vec::new();
+        };
+        (lines.start..lines.end.min(self.line_count()))
+            .flat_map(|y| {
+                let line = self.line_at(y);
+                regex
+                    .find_iter(&line)
+                    .filter_map(Result::ok)
+                    .filter(|mat| !mat.as_str().is_empty())
+                    .map(|mat| {
+                        let start = line[..mat.start()].chars().count();
+                        (y, start..start + mat.as_str().chars().count())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+/// Runtime options changed through `:set`, the config file gives their starting values
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Tints the background of the cursor line
+    pub cursorline: bool,
+    /// Tints the background of the cursor column
+    pub cursorcolumn: bool,
+    /// Shows tabs, trailing spaces and line ends
+    pub list: bool,
+    /// Folds long lines onto more rows instead of scrolling sideways
+    pub wrap: bool,
+    /// Screen column, counting from 1, tinted as a guide for line length
+    pub colorcolumn: Option<u16>,
+    /// Line numbers in a gutter left of the text
+    pub number: bool,
+    pub relativenumber: bool,
+    /// Forces a syntax instead of picking one from the file extension
+    #[serde(skip)]
+    pub syntax: Option<String>,
+    /// Columns between tab stops when drawing a `\t`
+    pub tabwidth: usize,
+    /// Spaces `>>` and `<<` add or remove
+    pub shiftwidth: usize,
+    /// Start new lines with the indent of the line above
+    pub autoindent: bool,
+    /// Tab inserts spaces up to the next tab stop instead of a `\t`
+    pub expandtab: bool,
+    /// Typing an opening bracket or quote adds the closing one after the cursor
+    pub autopairs: bool,
+    /// Re-read the theme file when it changes on disk
+    pub autoreload: bool,
+    /// Lines kept visible above and below the cursor when scrolling
+    pub scrolloff: usize,
+    /// Milliseconds to wait for the next key of a sequence in the keymap like `gg`
+    pub timeoutlen: usize,
+    /// Milliseconds an info or warning message stays on screen, errors stay until a key
+    pub messagetimeout: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            cursorline: false,
+            cursorcolumn: false,
+            list: false,
+            wrap: false,
+            colorcolumn: None,
+            number: false,
+            relativenumber: false,
+            syntax: None,
+            tabwidth: 4,
+            shiftwidth: 4,
+            autoindent: true,
+            expandtab: true,
+            autopairs: false,
+            autoreload: false,
+            scrolloff: 3,
+            timeoutlen: 1000,
+            messagetimeout: 4000,
+        }
+    }
+}
+
+/// A `:set` argument naming an option that doesn't exist, shown as a warning rather than an error
+#[derive(Debug)]
+pub struct UnknownOption(pub String);
+
+impl std::fmt::Display for UnknownOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown option `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOption {}
+
+impl Settings {
+    /// Applies every argument of a `:set` like `nu noet ts=2`, stopping at the first bad one
+    pub fn set(&mut self, args: &str) -> anyhow::Result<()> {
+        args.split_whitespace()
+            .try_for_each(|arg| self.set_one(arg))
+    }
+
+    /// Applies a single `:set` argument: `option` and `nooption` turn an on/off option on or
+    /// off, `option!` flips it and `option=value` sets a number or the syntax
+    fn set_one(&mut self, arg: &str) -> anyhow::Result<()> {
+        if let Some((name, value)) = arg.split_once('=') {
+            match name {
+                "syntax" => {
+                    self.syntax = (!value.is_empty()).then(|| value.to_string());
+                    return Ok(());
+                }
+                "colorcolumn" | "cc" => {
+                    let column: u16 = value.parse().context("colorcolumn must be a number")?;
+                    self.colorcolumn = (column > 0).then_some(column);
+                    return Ok(());
+                }
+                _ => {}
+            }
+            let Some((name, field, min)) = self.number_option(name) else {
+                if self.flag(name).is_some() {
+                    bail!("{name} doesn't take a value");
+                }
+                return Err(UnknownOption(name.to_string()).into());
+            };
+            let n: usize = value
+                .parse()
+                .with_context(|| format!("{name} must be a number"))?;
+            if n < min {
+                bail!("{name} must be at least {min}");
+            }
+            *field = n;
+        } else if let Some(flag) = arg.strip_suffix('!').and_then(|name| self.flag(name)) {
+            *flag = !*flag;
+        } else if let Some(flag) = self.flag(arg) {
+            *flag = true;
+        } else if let Some(flag) = arg.strip_prefix("no").and_then(|name| self.flag(name)) {
+            *flag = false;
+        } else if matches!(arg, "colorcolumn" | "cc") {
+            bail!("colorcolumn needs a value like colorcolumn=80");
+        } else if let Some((name, _, _)) = self.number_option(arg) {
+            bail!("{name} needs a value like {name}=4");
+        } else {
+            return Err(UnknownOption(arg.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// The on/off option called `name` or its short name
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "cursorline" | "cul" => &mut self.cursorline,
+            "cursorcolumn" | "cuc" => &mut self.cursorcolumn,
+            "list" => &mut self.list,
+            "wrap" => &mut self.wrap,
+            "number" | "nu" => &mut self.number,
+            "relativenumber" | "rnu" => &mut self.relativenumber,
+            "autoindent" | "ai" => &mut self.autoindent,
+            "expandtab" | "et" => &mut self.expandtab,
+            "autopairs" | "ap" => &mut self.autopairs,
+            "autoreload" | "ar" => &mut self.autoreload,
+            _ => return None,
+        })
+    }
+
+    /// The number option called `name` or its short name, with its full name and smallest value
+    fn number_option(&mut self, name: &str) -> Option<(&'static str, &mut usize, usize)> {
+        Some(match name {
+            "tabwidth" | "tabstop" | "ts" => ("tabwidth", &mut self.tabwidth, 1),
+            "shiftwidth" | "sw" => ("shiftwidth", &mut self.shiftwidth, 1),
+            "scrolloff" | "so" => ("scrolloff", &mut self.scrolloff, 0),
+            "timeoutlen" | "tm" => ("timeoutlen", &mut self.timeoutlen, 0),
+            "messagetimeout" | "mt" => ("messagetimeout", &mut self.messagetimeout, 0),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut settings = Settings::default();
+        settings.set("nu noet ts=2 syntax=py").unwrap();
+        assert!(settings.number);
+        assert!(!settings.expandtab);
+        assert_eq!(settings.tabwidth, 2);
+        assert_eq!(settings.syntax.as_deref(), Some("py"));
+
+        settings.set("nu! rnu! syntax=").unwrap();
+        assert!(!settings.number);
+        assert!(settings.relativenumber);
+        assert_eq!(settings.syntax, None);
+
+        let unknown = settings.set("nope").unwrap_err();
+        assert!(unknown.downcast_ref::<UnknownOption>().is_some());
+        assert!(settings.set("nots").unwrap_err().is::<UnknownOption>());
+        for bad in ["sw=0", "ts=x", "nu=1", "ts"] {
+            let err = settings.set(bad).unwrap_err();
+            assert!(!err.is::<UnknownOption>(), "{bad}");
+        }
+        assert_eq!(settings.shiftwidth, 4);
+
+        settings.set("cc=80").unwrap();
+        assert_eq!(settings.colorcolumn, Some(80));
+        settings.set("colorcolumn=0").unwrap();
+        assert_eq!(settings.colorcolumn, None);
+        assert!(settings.set("cc=-1").is_err());
+    }
+}
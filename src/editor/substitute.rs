@@ -0,0 +1,198 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use fancy_regex::Regex;
+
+/// A parsed `:s/pattern/replacement/flags` command
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub pattern: Regex,
+    pub replacement: String,
+    pub global: bool,
+    pub whole_file: bool,
+}
+
+/// A line a [`Substitution`] matched on, with the replacements made
+#[derive(Debug, PartialEq, Eq)]
+pub struct Substituted {
+    pub text: String,
+    pub count: usize,
+}
+
+impl Substitution {
+    /// Whether `cmd` is shaped like a substitution, `s` or `%s` followed by a delimiter
+    pub fn is_candidate(cmd: &str) -> bool {
+        let cmd = cmd.strip_prefix('%').unwrap_or(cmd);
+        let mut chars = cmd.chars();
+        chars.next() == Some('s') && chars.next().is_some_and(is_delimiter)
+    }
+
+    /// Applies the substitution to one line without its line break, `None` if it doesn't match
+    pub fn apply(&self, line: &str) -> anyhow::Result<Option<Substituted>> {
+        let matches = self.pattern.find_iter(line).filter(Result::is_ok).count();
+        if matches == 0 {
+            return Ok(None);
+        }
+        let limit = if self.global { 0 } else { 1 };
+        let text = self
+            .pattern
+            .try_replacen(line, limit, self.replacement.as_str())
+            .map_err(|e| anyhow!("{e}"))?;
+        Ok(Some(Substituted {
+            text: text.into_owned(),
+            count: if self.global { matches } else { 1 },
+        }))
+    }
+}
+
+/// Turns vim style `\1` group references into `${1}` so the regex crate expands them,
+/// `$1` and `${name}` keep working as they are
+fn expand_group_refs(replacement: &str) -> String {
+    let mut res = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(digit)) if digit.is_ascii_digit() => {
+                res.push_str(&format!("${{{digit}}}"));
+                chars.next();
+            }
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn is_delimiter(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && c != '\\'
+}
+
+/// Splits on `delim`, a backslash escaped delimiter becomes a literal one
+fn split_unescaped(s: &str, delim: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delim => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    let part = parts.last_mut().unwrap();
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            c if c == delim => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+impl FromStr for Substitution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole_file, s) = s.strip_prefix('%').map_or((false, s), |rest| (true, rest));
+        let Some(s) = s.strip_prefix('s') else {
+            bail!("not a substitution");
+        };
+        let Some(delim) = s.chars().next().filter(|c| is_delimiter(*c)) else {
+            bail!("missing delimiter");
+        };
+        let parts = split_unescaped(&s[delim.len_utf8()..], delim);
+        if parts.len() == 1 {
+            bail!("unterminated pattern");
+        }
+        if parts.len() > 3 {
+            bail!("trailing characters");
+        }
+        let pattern = parts[0].as_str();
+        if pattern.is_empty() {
+            bail!("empty pattern");
+        }
+        let mut global = false;
+        for flag in parts.get(2).map(String::as_str).unwrap_or_default().chars() {
+            match flag {
+                'g' => global = true,
+                other => bail!("unknown flag `{other}`"),
+            }
+        }
+        Ok(Self {
+            pattern: Regex::new(pattern).map_err(|e| anyhow!("{e}"))?,
+            replacement: expand_group_refs(&parts[1]),
+            global,
+            whole_file,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let sub: Substitution = "%s/a\\/b/c/g".parse().unwrap();
+        assert_eq!(sub.pattern.as_str(), "a/b");
+        assert_eq!(sub.replacement, "c");
+        assert!(sub.global);
+        assert!(sub.whole_file);
+
+        let sub: Substitution = "s#x#y".parse().unwrap();
+        assert_eq!(sub.pattern.as_str(), "x");
+        assert_eq!(sub.replacement, "y");
+        assert!(!sub.global);
+        assert!(!sub.whole_file);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("s/(/x/".parse::<Substitution>().is_err());
+        assert!("s//x/".parse::<Substitution>().is_err());
+        assert!("s/a/b/q".parse::<Substitution>().is_err());
+        assert!("s/a/b/g/".parse::<Substitution>().is_err());
+        assert!("s/abc".parse::<Substitution>().is_err());
+    }
+
+    #[test]
+    fn test_candidate() {
+        assert!(Substitution::is_candidate("s/a/b/"));
+        assert!(Substitution::is_candidate("%s|a|b|"));
+        assert!(!Substitution::is_candidate("set number"));
+        assert!(!Substitution::is_candidate("s"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let sub: Substitution = "s/a/b/".parse().unwrap();
+        assert_eq!(
+            sub.apply("aa").unwrap(),
+            Some(Substituted {
+                text: "ba".into(),
+                count: 1,
+            })
+        );
+        assert_eq!(sub.apply("cc").unwrap(), None);
+
+        let sub: Substitution = "%s/a/b/g".parse().unwrap();
+        assert_eq!(
+            sub.apply("aa").unwrap(),
+            Some(Substituted {
+                text: "bb".into(),
+                count: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        let sub: Substitution = r"s/(\w+) (\w+)/\2 \1/".parse().unwrap();
+        assert_eq!(
+            sub.apply("hello world").unwrap().unwrap().text,
+            "world hello"
+        );
+
+        let sub: Substitution = r"s/(?<x>\d+)/<${x}$1>/".parse().unwrap();
+        assert_eq!(sub.apply("a 12").unwrap().unwrap().text, "a <1212>");
+    }
+}
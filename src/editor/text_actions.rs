@@ -1,38 +1,240 @@
-use crate::editor::{Editor, Position};
+use crate::editor::{
+    Editor, Position,
+    cursor_actions::{CursorAction, next_grapheme},
+};
 
 pub trait TextAction {
     fn insert_char(&mut self, pos: Position, c: char);
+    fn insert_str(&mut self, pos: Position, s: &str);
     fn remove_char(&mut self, pos: Position);
+    fn delete_range(&mut self, start: Position, end: Position) -> String;
+    fn text_range(&self, start: Position, end: Position) -> String;
+    fn replace_range(&mut self, start: Position, end: Position, s: &str);
+    fn join_lines(&mut self, y: usize) -> Option<Position>;
+    fn get_char_offset(&self, pos: Position) -> usize;
     fn get_byte_offset(&self, pos: Position) -> usize;
 }
 
 impl TextAction for Editor {
     fn insert_char(&mut self, pos: Position, c: char) {
-        self.file_text.insert(self.get_byte_offset(pos), c);
+        self.mark_modified();
+        self.file_text.insert_char(self.get_char_offset(pos), c);
     }
 
+    fn insert_str(&mut self, pos: Position, s: &str) {
+        self.mark_modified();
+        self.file_text.insert(self.get_char_offset(pos), s);
+    }
+
+    /// Removes the grapheme cluster at `pos` so an accent goes with its letter, past the end of
+    /// the line it's the line break and past the end of the text nothing
     fn remove_char(&mut self, pos: Position) {
-        let byte_offset = self.get_byte_offset(pos);
-        if byte_offset >= self.file_text.len() {
-            self.file_text.pop();
+        let start = self.get_char_offset(pos);
+        if start >= self.file_text.len_chars() {
             return;
         }
-        self.file_text.remove(self.get_byte_offset(pos));
+        let line = self.line_at(pos.y);
+        let end = if pos.x < line.chars().count() {
+            let x = next_grapheme(&line, pos.x);
+            self.get_char_offset(Position { x, ..pos })
+        } else {
+            start + 1
+        };
+        self.mark_modified();
+        self.file_text.remove(start..end);
+    }
+
+    /// Removes the text from `start` up to but not including `end` and returns it
+    fn delete_range(&mut self, start: Position, end: Position) -> String {
+        let start = self.get_char_offset(start);
+        let end = self.get_char_offset(end);
+        if start >= end {
+            return String::new();
+        }
+        self.mark_modified();
+        let removed = self.file_text.slice(start..end).to_string();
+        self.file_text.remove(start..end);
+        removed
+    }
+
+    /// Copies the text from `start` up to but not including `end`
+    fn text_range(&self, start: Position, end: Position) -> String {
+        let start = self.get_char_offset(start);
+        let end = self.get_char_offset(end).max(start);
+        self.file_text.slice(start..end).to_string()
+    }
+
+    /// Replaces the text from `start` up to but not including `end` with `s` as one edit
+    fn replace_range(&mut self, start: Position, end: Position, s: &str) {
+        let start = self.get_char_offset(start);
+        let end = self.get_char_offset(end).max(start);
+        self.file_text.remove(start..end);
+        self.file_text.insert(start, s);
+        self.mark_modified();
+    }
+
+    /// Joins line `y + 1` onto line `y` with a single space in between, dropping the leading
+    /// whitespace of the joined line. No space is added next to an empty or blank-ended line.
+    /// Returns where the lines were joined, `None` on the last line
+    fn join_lines(&mut self, y: usize) -> Option<Position> {
+        if y >= self.line_count() {
+            return None;
+        }
+        let line = self.line_at(y);
+        let ends_blank = line.is_empty() || line.ends_with([' ', '\t']);
+        let join = Position {
+            x: line.chars().count(),
+            y,
+        };
+        let newline = self.get_char_offset(join);
+        let mut next = self.file_text.get_chars_at(newline)?;
+        if next.next() != Some('\n') {
+            return None;
+        }
+        let mut next = next.peekable();
+        let mut blanks = 0;
+        while next.next_if(|c| matches!(c, ' ' | '\t')).is_some() {
+            blanks += 1;
+        }
+        let sep = match next.peek() {
+            None if blanks == 0 => return None,
+            None | Some('\n') => "",
+            Some(_) if ends_blank => "",
+            Some(_) => " ",
+        };
+        self.file_text.remove(newline..=newline + blanks);
+        self.file_text.insert(newline, sep);
+        self.mark_modified();
+        Some(join)
+    }
+
+    /// Char index of `pos`, a column past the end of the line is its end before the line break
+    /// and a line past the end of the file is the end of the text
+    fn get_char_offset(&self, pos: Position) -> usize {
+        let y = pos.y;
+        if y >= self.file_text.len_lines() {
+            return self.file_text.len_chars();
+        }
+        let line = self.file_text.line(y);
+        let mut len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len -= 1;
+            // a `\r` before it is part of a CRLF line break
+            if len > 0 && line.char(len - 1) == '\r' {
+                len -= 1;
+            }
+        }
+        self.file_text.line_to_char(y) + pos.x.min(len)
     }
 
     fn get_byte_offset(&self, pos: Position) -> usize {
-        let mut offset = 0usize;
-        for (i, line) in self.file_text.lines().enumerate() {
-            if i == pos.y as usize {
-                let x = pos.x as usize;
-                offset += line
-                    .char_indices()
-                    .nth(x)
-                    .map_or(line.len(), |(byte_idx, _)| byte_idx);
-                break;
+        self.file_text.char_to_byte(self.get_char_offset(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(text: &str, positions: &[(usize, usize)]) -> Vec<usize> {
+        let editor = Editor {
+            file_text: text.into(),
+            ..Default::default()
+        };
+        positions
+            .iter()
+            .map(|&(x, y)| editor.get_byte_offset(Position { x, y }))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_byte_offset() {
+        assert_eq!(offsets("", &[(0, 0), (3, 0), (0, 5)]), [0, 0, 0]);
+        // no trailing newline, the last line still ends at the end of the text
+        assert_eq!(
+            offsets(
+                "ab\ncd",
+                &[(0, 0), (2, 0), (9, 0), (0, 1), (1, 1), (9, 1), (0, 2)]
+            ),
+            [0, 2, 2, 3, 4, 5, 5]
+        );
+        // past the last line is the end of the text, not the start
+        assert_eq!(offsets("ab\ncd\n", &[(0, 2), (4, 2), (0, 9)]), [6, 6, 6]);
+        assert_eq!(
+            offsets(
+                "ab\r\ncd\r\nef",
+                &[(2, 0), (9, 0), (0, 1), (2, 1), (0, 2), (9, 2)]
+            ),
+            [2, 2, 4, 6, 8, 10]
+        );
+        assert_eq!(
+            offsets("äö\n好x", &[(1, 0), (2, 0), (9, 0), (0, 1), (1, 1), (2, 1)]),
+            [2, 4, 4, 5, 8, 9]
+        );
+    }
+
+    #[test]
+    fn test_edit_last_line() {
+        for (text, appended, removed) in [
+            ("ab\ncd", "ab\ncd!", "ab\nc"),
+            ("ab\ncd\n", "ab\ncd!\n", "ab\nc\n"),
+        ] {
+            let mut editor = Editor {
+                file_text: text.into(),
+                ..Default::default()
+            };
+            editor.insert_char(Position { x: 2, y: 1 }, '!');
+            assert_eq!(editor.file_text, appended, "{text:?}");
+            editor.remove_char(Position { x: 2, y: 1 });
+            editor.remove_char(Position { x: 1, y: 1 });
+            assert_eq!(editor.file_text, removed, "{text:?}");
+            editor.insert_str(Position { x: 0, y: 1 }, "x");
+            assert_eq!(editor.line_at(1), "xc", "{text:?}");
+            assert_eq!(editor.line_at(0), "ab", "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_remove_grapheme() {
+        let text = "e\u{301}🇩🇪👨\u{200d}👧\n";
+        // columns are chars, a cluster starts at the first of its chars
+        assert_eq!(offsets(text, &[(2, 0), (4, 0), (7, 0)]), [3, 11, 22]);
+        let mut editor = Editor {
+            file_text: text.into(),
+            ..Default::default()
+        };
+        editor.remove_char(Position { x: 2, y: 0 });
+        assert_eq!(editor.file_text, "e\u{301}👨\u{200d}👧\n");
+        editor.remove_char(Position { x: 2, y: 0 });
+        editor.remove_char(Position { x: 0, y: 0 });
+        assert_eq!(editor.file_text, "\n");
+    }
+
+    #[test]
+    fn test_remove_past_the_end() {
+        for text in ["aé", "aé\n"] {
+            let mut editor = Editor {
+                file_text: text.into(),
+                ..Default::default()
+            };
+            for pos in [(2, 0), (9, 0), (0, 1), (0, 5)] {
+                editor.remove_char(Position { x: pos.0, y: pos.1 });
             }
-            offset += line.len() + 1;
+            assert_eq!(editor.file_text, text.trim_end(), "{text:?}");
+            assert_eq!(editor.modified, text.ends_with('\n'), "{text:?}");
+            editor.remove_char(Position { x: 1, y: 0 });
+            assert_eq!(editor.file_text, "a", "{text:?}");
         }
-        offset
+    }
+
+    #[test]
+    fn test_append_to_crlf_line() {
+        let mut editor = Editor {
+            file_text: "ab\r\ncd\r\n".into(),
+            ..Default::default()
+        };
+        editor.insert_str(Position { x: 9, y: 0 }, "!");
+        editor.insert_str(Position { x: 9, y: 1 }, "?");
+        assert_eq!(editor.file_text, "ab!\r\ncd?\r\n");
     }
 }
@@ -1,4 +1,8 @@
-use std::{ops::Deref, str::FromStr, sync::LazyLock};
+use std::{
+    ops::{Deref, Range},
+    str::FromStr,
+    sync::LazyLock,
+};
 
 use anyhow::{Error, anyhow};
 use fancy_regex::Regex;
@@ -8,6 +12,7 @@ use ratatui::{
 };
 use serde::Deserialize;
 use serde_with::{self, DisplayFromStr, serde_as};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::theme::ColourTheme;
 
@@ -25,6 +30,101 @@ pub static RUST_SYNTAX: LazyLock<SyntaxRegex> = LazyLock::new(|| {
     .unwrap()
 });
 
+/// Regex that can never match, for categories a language doesn't have
+const NEVER: &str = r"^[^\s\S]";
+
+pub static PYTHON_SYNTAX: LazyLock<SyntaxRegex> = LazyLock::new(|| {
+    SyntaxRegex::new(
+        r"^(def|class|return|if|elif|else|for|while|in|not|and|or|is|import|from|as|with|try|except|finally|raise|pass|break|continue|lambda|yield|global|nonlocal|del|assert|async|await|None|True|False|self)\b",
+        r"^[A-Za-z_][A-Za-z0-9_]*",
+        r"^(\(|\)|\{|\}|\[|\]|;|:|,|\.)",
+        r#"^([rbfu]?\"(\\.|[^\"\\])*\"|[rbfu]?'(\\.|[^'\\])*'|[0-9]+(\.[0-9]+)?)"#,
+        r"^([A-Z][A-Za-z0-9_]*|int|str|float|bool|list|dict|set|tuple|bytes)\b",
+        r"^(==|!=|<=|>=|->|\*\*|//|=|\+|-|\*|/|%|<|>|@)",
+        r"^([A-Za-z_][A-Za-z0-9_]*)(?=\()",
+        r"^#.*",
+    )
+    .unwrap()
+});
+
+pub static JSON_SYNTAX: LazyLock<SyntaxRegex> = LazyLock::new(|| {
+    SyntaxRegex::new(
+        r"^(true|false|null)\b",
+        r"^[A-Za-z_][A-Za-z0-9_]*",
+        r"^(\{|\}|\[|\]|:|,)",
+        r#"^(\"(\\.|[^\"\\])*\"|-?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?)"#,
+        NEVER,
+        NEVER,
+        NEVER,
+        NEVER,
+    )
+    .unwrap()
+});
+
+pub static TOML_SYNTAX: LazyLock<SyntaxRegex> = LazyLock::new(|| {
+    SyntaxRegex::new(
+        r"^(true|false)\b",
+        r"^[A-Za-z0-9_-]+",
+        r"^(\[|\]|\{|\}|,|\.)",
+        r#"^('''.*|\"(\\.|[^\"\\])*\"|'[^']*'|-?[0-9][0-9_.:TZ+-]*)"#,
+        r#"^\[\[?[A-Za-z0-9_.\" -]+\]\]?"#,
+        r"^=",
+        NEVER,
+        r"^#.*",
+    )
+    .unwrap()
+});
+
+/// Looks up a syntax built into the binary by extension or name, e.g. `py` or `python`.
+/// Used when there's no `./syntax/{lang}.toml`
+pub fn builtin_syntax(lang: &str) -> Option<&'static SyntaxRegex> {
+    match lang_to_extension(lang) {
+        "rs" => Some(&RUST_SYNTAX),
+        "py" => Some(&PYTHON_SYNTAX),
+        "json" => Some(&JSON_SYNTAX),
+        "toml" => Some(&TOML_SYNTAX),
+        _ => None,
+    }
+}
+
+static MODELINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:vi|vim|ex):.*?\b(?:ft|filetype|syntax)=([A-Za-z0-9_+-]+)").unwrap()
+});
+
+/// Maps an interpreter or vim filetype name to the extension its syntax file is named after
+pub fn lang_to_extension(lang: &str) -> &str {
+    match lang {
+        "python" => "py",
+        "node" | "nodejs" | "javascript" => "js",
+        "bash" | "zsh" | "dash" | "ksh" => "sh",
+        "ruby" => "rb",
+        "perl" => "pl",
+        "rust" | "rust-script" => "rs",
+        "text" => "txt",
+        other => other,
+    }
+}
+
+/// Picks a syntax from a `#!` line or a vim modeline in the first or last five lines
+pub fn detect_syntax(text: &str) -> Option<String> {
+    if let Some(shebang) = text.lines().next().and_then(|l| l.strip_prefix("#!")) {
+        let mut args = shebang.split_whitespace();
+        let mut interpreter = args.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            interpreter = args.find(|arg| !arg.starts_with('-'))?;
+        }
+        let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        return Some(lang_to_extension(interpreter).to_string());
+    }
+    let line_count = text.lines().count();
+    text.lines()
+        .enumerate()
+        .filter(|(idx, _)| *idx < 5 || *idx + 5 >= line_count)
+        .find_map(|(_, line)| MODELINE.captures(line).ok().flatten())
+        .and_then(|caps| caps.get(1))
+        .map(|lang| lang_to_extension(lang.as_str()).to_string())
+}
+
 #[derive(Debug, Clone)]
 pub struct CRegex(Regex);
 
@@ -78,7 +178,25 @@ pub struct SyntaxRegex {
     pub extra: CRegex,
 }
 
-pub fn colour_text<'a>(text: &'a str, theme: &ColourTheme, syntax: &SyntaxRegex) -> Text<'a> {
+/// How a buffer gets split into tokens, plain text is one [`SyntaxKind::Unknown`] span per line
+#[derive(Debug, Clone, Default)]
+pub enum Syntax {
+    Regex(Box<SyntaxRegex>),
+    #[default]
+    Plain,
+}
+
+impl Syntax {
+    pub fn parse<'a>(&self, text: &'a str) -> Vec<(&'a str, SyntaxKind)> {
+        match self {
+            Self::Regex(syntax) => syntax.parse(text),
+            Self::Plain if text.is_empty() => Vec::new(),
+            Self::Plain => vec![(text, SyntaxKind::Unknown)],
+        }
+    }
+}
+
+pub fn colour_text<'a>(text: &'a str, theme: &ColourTheme, syntax: &Syntax) -> Text<'a> {
     let styled_lines: Vec<Line<'a>> = text
         .lines()
         .map(|line| {
@@ -89,12 +207,11 @@ pub fn colour_text<'a>(text: &'a str, theme: &ColourTheme, syntax: &SyntaxRegex)
                     Span::raw(*val).style(match kind {
                         SyntaxKind::Keyword => Style::new().fg(theme.keyword.into()),
                         SyntaxKind::Identifier => Style::new().fg(theme.ident.into()),
-                        SyntaxKind::Delimiter | SyntaxKind::Whitespace => {
-                            Style::new().fg(theme.delim.into())
-                        }
+                        SyntaxKind::Delimiter => Style::new().fg(theme.delim.into()),
                         SyntaxKind::Type => Style::new().fg(theme.types.into()),
-                        SyntaxKind::Extra | SyntaxKind::Unknown => {
-                            Style::new().fg(theme.extra.into())
+                        SyntaxKind::Extra => Style::new().fg(theme.extra.into()),
+                        SyntaxKind::Unknown | SyntaxKind::Whitespace => {
+                            Style::new().fg(theme.foreground().into())
                         }
                         SyntaxKind::Literal => Style::new().fg(theme.lit.into()),
                         SyntaxKind::Function => Style::new().fg(theme.function.into()),
@@ -108,6 +225,210 @@ pub fn colour_text<'a>(text: &'a str, theme: &ColourTheme, syntax: &SyntaxRegex)
     Text::from(styled_lines)
 }
 
+/// Replaces tabs with spaces up to the next tab stop, the buffer keeps the real `\t`. With a
+/// `marker` that's drawn in the first column of each tab
+pub fn expand_tabs(line: &mut Line<'_>, tabwidth: usize, marker: Option<char>) {
+    let tabwidth = tabwidth.max(1);
+    let mut col = 0;
+    for span in &mut line.spans {
+        if !span.content.contains('\t') {
+            col += span.content.width();
+            continue;
+        }
+        let mut expanded = String::with_capacity(span.content.len());
+        for c in span.content.chars() {
+            if c == '\t' {
+                let spaces = tabwidth - col % tabwidth;
+                expanded.extend(marker);
+                expanded.extend(std::iter::repeat_n(
+                    ' ',
+                    spaces - usize::from(marker.is_some()),
+                ));
+                col += spaces;
+            } else {
+                expanded.push(c);
+                col += c.width().unwrap_or_default();
+            }
+        }
+        span.content = expanded.into();
+    }
+}
+
+/// Display width of every char of `line` once tabs are expanded, a tab counts as one column per
+/// space it becomes. `eol` adds the column `:set list` draws its `¬` in
+pub fn expanded_widths(line: &str, tabwidth: usize, eol: bool) -> Vec<usize> {
+    let tabwidth = tabwidth.max(1);
+    let mut widths = Vec::with_capacity(line.len());
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = tabwidth - col % tabwidth;
+            widths.extend(std::iter::repeat_n(1, spaces));
+            col += spaces;
+        } else {
+            let width = c.width().unwrap_or_default();
+            widths.push(width);
+            col += width;
+        }
+    }
+    if eol {
+        widths.push(1);
+    }
+    widths
+}
+
+/// Where the screen rows start when chars of these widths get folded at `width` columns, as
+/// the char index and display column of each row's first char. A char that doesn't fit moves to
+/// the next row whole
+pub fn wrap_starts(widths: impl IntoIterator<Item = usize>, width: usize) -> Vec<(usize, usize)> {
+    let width = width.max(1);
+    let mut starts = vec![(0, 0)];
+    let mut col = 0;
+    let mut row_col = 0;
+    for (idx, w) in widths.into_iter().enumerate() {
+        if col + w - row_col > width && col > row_col {
+            starts.push((idx, col));
+            row_col = col;
+        }
+        col += w;
+    }
+    starts
+}
+
+/// Folds a line that's already had its tabs expanded into rows of at most `width` columns
+pub fn wrap_line(line: Line<'_>, width: usize) -> Vec<Line<'_>> {
+    let chars = line.spans.iter().flat_map(|span| span.content.chars());
+    let starts = wrap_starts(chars.map(|c| c.width().unwrap_or_default()), width);
+    let mut breaks = starts.iter().skip(1).map(|(idx, _)| *idx).peekable();
+    let mut rows = vec![Line::default()];
+    let mut idx = 0;
+    for span in line.spans {
+        let mut part = String::new();
+        for c in span.content.chars() {
+            if breaks.next_if_eq(&idx).is_some() {
+                if !part.is_empty() {
+                    let row = rows.last_mut().expect("rows start with one line");
+                    row.push_span(Span::styled(std::mem::take(&mut part), span.style));
+                }
+                rows.push(Line::default());
+            }
+            part.push(c);
+            idx += 1;
+        }
+        if !part.is_empty() {
+            let row = rows.last_mut().expect("rows start with one line");
+            row.push_span(Span::styled(part, span.style));
+        }
+    }
+    rows
+}
+
+/// Drops the first `cols` display columns of a line that's already had its tabs expanded, a
+/// wide char cut in half leaves a space so everything after it stays in its column
+pub fn skip_columns(line: Line<'_>, cols: usize) -> Line<'_> {
+    if cols == 0 {
+        return line;
+    }
+    let mut col = 0;
+    let mut spans = Vec::with_capacity(line.spans.len());
+    for span in line.spans {
+        let mut part = String::new();
+        for c in span.content.chars() {
+            let width = c.width().unwrap_or_default();
+            if col >= cols {
+                part.push(c);
+            } else if col + width > cols {
+                part.extend(std::iter::repeat_n(' ', col + width - cols));
+            }
+            col += width;
+        }
+        if !part.is_empty() {
+            spans.push(Span::styled(part, span.style));
+        }
+    }
+    Line::from(spans).style(line.style)
+}
+
+/// `:set list`: tabs and trailing spaces get `style`, the spaces turn into `·` and a `¬` marks
+/// the end of the line. The char count up to the end stays the same so columns still line up,
+/// tabs get their `→` from [`expand_tabs`]
+pub fn show_invisibles(line: &mut Line<'_>, style: Style) {
+    let chars: Vec<char> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars())
+        .collect();
+    let len = chars.len();
+    let trailing_start = len - chars.iter().rev().take_while(|c| **c == ' ').count();
+    let tabs = chars.iter().enumerate().filter(|(_, c)| **c == '\t');
+    for (tab, _) in tabs {
+        patch_range_style(line, tab..tab + 1, style);
+    }
+    patch_range_style(line, trailing_start..len, style);
+
+    let mut offset = 0;
+    for span in &mut line.spans {
+        let span_len = span.content.chars().count();
+        if offset + span_len > trailing_start {
+            let dotted: String = span
+                .content
+                .chars()
+                .enumerate()
+                .map(|(idx, c)| {
+                    if offset + idx >= trailing_start {
+                        '·'
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            span.content = dotted.into();
+        }
+        offset += span_len;
+    }
+    line.spans.push(Span::styled("¬", style));
+}
+
+/// Patches `style` onto the chars of `line` inside `range`, splitting spans where needed
+pub fn patch_range_style(line: &mut Line<'_>, range: Range<usize>, style: Style) {
+    let mut spans = Vec::with_capacity(line.spans.len() + 2);
+    let mut offset = 0;
+    for span in line.spans.drain(..) {
+        let len = span.content.chars().count();
+        let start = range.start.clamp(offset, offset + len) - offset;
+        let end = range.end.clamp(offset, offset + len) - offset;
+        offset += len;
+        if start == end {
+            spans.push(span);
+            continue;
+        }
+        let content = span.content.as_ref();
+        let byte_at = |idx: usize| {
+            content
+                .char_indices()
+                .nth(idx)
+                .map_or(content.len(), |(byte_idx, _)| byte_idx)
+        };
+        let (start, end) = (byte_at(start), byte_at(end));
+        for (part, patched) in [
+            (&content[..start], false),
+            (&content[start..end], true),
+            (&content[end..], false),
+        ] {
+            if part.is_empty() {
+                continue;
+            }
+            let part_style = if patched {
+                span.style.patch(style)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(part.to_string(), part_style));
+        }
+    }
+    line.spans = spans;
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SyntaxKind {
     Keyword,
@@ -123,6 +444,7 @@ pub enum SyntaxKind {
 }
 
 impl SyntaxRegex {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         keyword: &str,
         identifier: &str,
@@ -161,8 +483,6 @@ impl SyntaxRegex {
                 break;
             }
 
-            let mut matched_any = false;
-
             macro_rules! try_rule {
                 ($regex:expr, $kind:expr) => {{
                     if let Ok(Some(m)) = $regex.find(input) {
@@ -175,14 +495,12 @@ impl SyntaxRegex {
                                 let (tok, rest) = input.split_at(len);
                                 tokens.push((tok, SyntaxKind::Unknown));
                                 input = rest;
-                                matched_any = true;
                                 continue;
                             }
 
                             let (tok, rest) = input.split_at(end);
                             tokens.push((tok, $kind));
                             input = rest;
-                            matched_any = true;
                             continue;
                         }
                     }
@@ -198,13 +516,11 @@ impl SyntaxRegex {
             try_rule!(self.extra, SyntaxKind::Extra);
             try_rule!(self.delimiters, SyntaxKind::Delimiter);
 
-            if !matched_any {
-                let ch = input.chars().next().unwrap_or_default();
-                let len = ch.len_utf8();
-                let (tok, rest) = input.split_at(len);
-                tokens.push((tok, SyntaxKind::Unknown));
-                input = rest;
-            }
+            let ch = input.chars().next().unwrap_or_default();
+            let len = ch.len_utf8();
+            let (tok, rest) = input.split_at(len);
+            tokens.push((tok, SyntaxKind::Unknown));
+            input = rest;
         }
 
         tokens
@@ -311,6 +627,121 @@ mod tests {
         assert!(tokens.contains(&("}", SyntaxKind::Delimiter)));
     }
 
+    #[test]
+    fn test_expand_tabs() {
+        let mut line = Line::from(vec![Span::raw("a\t"), Span::raw("\tb"), Span::raw("好\t")]);
+        expand_tabs(&mut line, 4, None);
+        let expanded: String = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(expanded, "a       b好 ");
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(expanded_widths("a\t好", 4, false), [1, 1, 1, 1, 2]);
+        assert_eq!(expanded_widths("好\t", 4, false), [2, 1, 1]);
+        assert_eq!(expanded_widths("", 4, true), [1]);
+        assert_eq!(wrap_starts([1, 1, 1, 1, 2], 5), [(0, 0), (4, 4)]);
+        assert_eq!(wrap_starts([], 5), [(0, 0)]);
+        assert_eq!(wrap_starts([2, 2], 1), [(0, 0), (1, 2)]);
+
+        let line = Line::from(vec![Span::raw("abc"), Span::raw("de好fg")]);
+        let rows: Vec<String> = wrap_line(line, 4)
+            .iter()
+            .map(|row| row.spans.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(rows, ["abcd", "e好f", "g"]);
+        assert_eq!(wrap_line(Line::default(), 4).len(), 1);
+    }
+
+    #[test]
+    fn test_show_invisibles() {
+        use ratatui::style::Color;
+
+        let dim = Style::new().fg(Color::DarkGray);
+        let mut line = Line::from(vec![
+            Span::raw("\tx = 1; "),
+            Span::raw("\t "),
+            Span::raw(" "),
+        ]);
+        show_invisibles(&mut line, dim);
+        expand_tabs(&mut line, 4, Some('→'));
+        let shown: String = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(shown, "→   x = 1; →··¬");
+        assert_eq!(line.spans[0].style, dim);
+        assert_eq!(line.spans[1].content, "x = 1; ");
+        assert_eq!(line.spans[1].style, Style::default());
+        assert!(line.spans[2..].iter().all(|span| span.style == dim));
+
+        let mut empty = Line::default();
+        show_invisibles(&mut empty, dim);
+        assert_eq!(empty.spans.len(), 1);
+    }
+
+    #[test]
+    fn test_builtin_syntaxes() {
+        for lang in ["rs", "py", "json", "toml"] {
+            assert!(builtin_syntax(lang).is_some(), "{lang}");
+        }
+        assert!(builtin_syntax("python").is_some());
+        assert!(builtin_syntax("txt").is_none());
+
+        let tokens = non_ws(PYTHON_SYNTAX.parse("def f(x): # (\"\")"));
+        assert_eq!(
+            tokens,
+            vec![
+                ("def", SyntaxKind::Keyword),
+                ("f", SyntaxKind::Function),
+                ("(", SyntaxKind::Delimiter),
+                ("x", SyntaxKind::Identifier),
+                (")", SyntaxKind::Delimiter),
+                (":", SyntaxKind::Delimiter),
+                ("# (\"\")", SyntaxKind::Comment),
+            ]
+        );
+
+        let tokens = non_ws(TOML_SYNTAX.parse("[package] name = \"x\" # y"));
+        assert_eq!(
+            tokens,
+            vec![
+                ("[package]", SyntaxKind::Type),
+                ("name", SyntaxKind::Identifier),
+                ("=", SyntaxKind::Extra),
+                ("\"x\"", SyntaxKind::Literal),
+                ("# y", SyntaxKind::Comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_syntax() {
+        assert_eq!(
+            Syntax::Plain.parse("fn main() {}"),
+            vec![("fn main() {}", SyntaxKind::Unknown)]
+        );
+        assert!(Syntax::Plain.parse("").is_empty());
+    }
+
+    #[test]
+    fn test_detect_syntax() {
+        assert_eq!(detect_syntax("#!/usr/bin/env python3\n"), Some("py".into()));
+        assert_eq!(detect_syntax("#!/bin/bash\necho"), Some("sh".into()));
+        assert_eq!(detect_syntax("#!/usr/bin/env -S node\n"), Some("js".into()));
+        assert_eq!(
+            detect_syntax("a\nb\n# vim: set ft=rust:\n"),
+            Some("rs".into())
+        );
+        assert_eq!(detect_syntax("// vi: syntax=txt"), Some("txt".into()));
+        assert_eq!(detect_syntax("just some text\n"), None);
+    }
+
     #[test]
     fn test_unknown_tokens() {
         let input = "@$?";
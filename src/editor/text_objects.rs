@@ -0,0 +1,225 @@
+use fancy_regex::Regex;
+
+use crate::editor::{
+    Editor, MotionRange, Position,
+    cursor_actions::{CursorAction, WORD_PATTERN},
+    text_actions::TextAction,
+};
+
+pub trait TextObject {
+    fn text_object_range(&self, object: char, around: bool) -> Option<MotionRange>;
+    fn word_object(&self, around: bool) -> Option<MotionRange>;
+    fn quote_object(&self, quote: char, around: bool) -> Option<MotionRange>;
+    fn bracket_object(&self, open: char, close: char, around: bool) -> Option<MotionRange>;
+}
+
+impl TextObject for Editor {
+    /// What the text object typed after `i` (`around = false`) or `a` covers, `None` when the
+    /// cursor isn't on or inside one
+    fn text_object_range(&self, object: char, around: bool) -> Option<MotionRange> {
+        match object {
+            'w' => self.word_object(around),
+            '"' | '\'' | '`' => self.quote_object(object, around),
+            '(' | ')' | 'b' => self.bracket_object('(', ')', around),
+            '{' | '}' | 'B' => self.bracket_object('{', '}', around),
+            '[' | ']' => self.bracket_object('[', ']', around),
+            '<' | '>' => self.bracket_object('<', '>', around),
+            _ => None,
+        }
+    }
+
+    /// `iw` is the run of chars under the cursor the word motions would jump over, `aw` adds
+    /// the blanks after it or, at the end of the line, the ones before it
+    fn word_object(&self, around: bool) -> Option<MotionRange> {
+        let line = self.line_at_cursor();
+        let x = self.cursor.x;
+        let runs = char_runs(&line, &WORD_PATTERN);
+        let idx = runs
+            .iter()
+            .position(|(start, end)| (*start..*end).contains(&x))?;
+        let (mut start, mut end) = runs[idx];
+        let is_blank = |(start, end): (usize, usize)| {
+            line.chars()
+                .skip(start)
+                .take(end - start)
+                .all(char::is_whitespace)
+        };
+        if around {
+            if let Some(&next) = runs.get(idx + 1).filter(|run| is_blank(**run)) {
+                end = next.1;
+            } else if let Some(&prev) = idx
+                .checked_sub(1)
+                .and_then(|i| runs.get(i))
+                .filter(|run| is_blank(**run))
+            {
+                start = prev.0;
+            }
+        }
+        let y = self.cursor.y;
+        Some(MotionRange::Chars(
+            Position { x: start, y },
+            Position { x: end, y },
+        ))
+    }
+
+    /// The pair of `quote`s on the cursor line around the cursor, or the first pair after it
+    /// like vim. Backslash escaped quotes don't count
+    fn quote_object(&self, quote: char, around: bool) -> Option<MotionRange> {
+        let line = self.line_at_cursor();
+        let mut quotes = Vec::new();
+        let mut escaped = false;
+        for (idx, c) in line.chars().enumerate() {
+            if c == quote && !escaped {
+                quotes.push(idx);
+            }
+            escaped = c == '\\' && !escaped;
+        }
+        let x = self.cursor.x;
+        let (open, close) = quotes
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|(_, close)| x <= *close)?;
+        let (start, end) = if around {
+            (open, close + 1)
+        } else {
+            (open + 1, close)
+        };
+        let y = self.cursor.y;
+        Some(MotionRange::Chars(
+            Position { x: start, y },
+            Position { x: end, y },
+        ))
+    }
+
+    /// The innermost `open`/`close` pair around the cursor, nesting is tracked across lines.
+    /// On a bracket the pair is the one that bracket belongs to
+    fn bracket_object(&self, open: char, close: char, around: bool) -> Option<MotionRange> {
+        let text = &self.file_text;
+        let cursor = self.get_char_offset(self.cursor);
+        let under_cursor = (cursor < text.len_chars()).then(|| text.char(cursor));
+
+        let open_idx = if under_cursor == Some(open) {
+            cursor
+        } else {
+            let mut depth = 0usize;
+            // on a closing bracket the scan starts before it so it finds its own partner
+            let mut chars = text.chars_at(cursor);
+            let mut idx = cursor;
+            loop {
+                let c = chars.prev()?;
+                idx -= 1;
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        break idx;
+                    }
+                    depth -= 1;
+                }
+            }
+        };
+
+        let mut depth = 0usize;
+        let close_idx = open_idx
+            + 1
+            + text.chars_at(open_idx + 1).position(|c| {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                }
+                false
+            })?;
+
+        let (start, end) = if around {
+            (open_idx, close_idx + 1)
+        } else {
+            (open_idx + 1, close_idx)
+        };
+        Some(MotionRange::Chars(
+            self.position_from_byte_offset(text.char_to_byte(start)),
+            self.position_from_byte_offset(text.char_to_byte(end)),
+        ))
+    }
+}
+
+/// Char ranges of the runs of `pattern` in `line`, chars it doesn't match are a run each
+fn char_runs(line: &str, pattern: &Regex) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut char_idx = 0;
+    let mut byte = 0;
+    let matches = pattern
+        .find_iter(line)
+        .filter_map(Result::ok)
+        .map(|mat| (mat.start(), mat.end()));
+    for (start, end) in matches.chain(std::iter::once((line.len(), line.len()))) {
+        for _ in line[byte..start].chars() {
+            runs.push((char_idx, char_idx + 1));
+            char_idx += 1;
+        }
+        let len = line[start..end].chars().count();
+        if len > 0 {
+            runs.push((char_idx, char_idx + len));
+            char_idx += len;
+        }
+        byte = end;
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(text: &str, cursor: Position, object: char, around: bool) -> Option<String> {
+        let editor = Editor {
+            file_text: text.into(),
+            cursor,
+            ..Default::default()
+        };
+        match editor.text_object_range(object, around)? {
+            MotionRange::Chars(start, end) => Some(editor.text_range(start, end)),
+            MotionRange::Lines(..) => None,
+        }
+    }
+
+    #[test]
+    fn test_word_objects() {
+        let at = |x| Position { x, y: 0 };
+        assert_eq!(range("foo bar baz", at(5), 'w', false).unwrap(), "bar");
+        assert_eq!(range("foo bar baz", at(5), 'w', true).unwrap(), "bar ");
+        assert_eq!(range("foo bar", at(5), 'w', true).unwrap(), " bar");
+        assert_eq!(range("a.b", at(1), 'w', false).unwrap(), ".");
+        assert_eq!(range("", at(0), 'w', false), None);
+    }
+
+    #[test]
+    fn test_quote_objects() {
+        let at = |x| Position { x, y: 0 };
+        let text = r#"say "hi \"there\"" and "bye""#;
+        assert_eq!(range(text, at(6), '"', false).unwrap(), r#"hi \"there\""#);
+        assert_eq!(range(text, at(6), '"', true).unwrap(), r#""hi \"there\"""#);
+        // before any quote the first pair on the line is used
+        assert_eq!(range(text, at(0), '"', false).unwrap(), r#"hi \"there\""#);
+        assert_eq!(range(text, at(26), '"', false).unwrap(), "bye");
+        assert_eq!(range(text, at(30), '"', false), None);
+        assert_eq!(range("it's", at(0), '\'', false), None);
+    }
+
+    #[test]
+    fn test_bracket_objects() {
+        let text = "fn a(b: (u8, u8)) {\n    x[0]\n}";
+        let at = |x, y| Position { x, y };
+        assert_eq!(range(text, at(9, 0), '(', false).unwrap(), "u8, u8");
+        assert_eq!(range(text, at(5, 0), ')', false).unwrap(), "b: (u8, u8)");
+        assert_eq!(range(text, at(16, 0), 'b', true).unwrap(), "(b: (u8, u8))");
+        assert_eq!(range(text, at(4, 0), '(', false).unwrap(), "b: (u8, u8)");
+        assert_eq!(range(text, at(6, 1), '{', false).unwrap(), "\n    x[0]\n");
+        assert_eq!(range(text, at(6, 1), '[', true).unwrap(), "[0]");
+        assert_eq!(range(text, at(0, 0), '(', false), None);
+        assert_eq!(range("((a)", at(0, 0), '(', false), None);
+    }
+}
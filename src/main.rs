@@ -1,17 +1,50 @@
 mod args;
 mod editor;
 mod theme;
-use crate::{args::Args, editor::Editor};
+use crate::{
+    args::Args,
+    editor::{Editor, keymap::Keymap},
+};
+
+use std::io::Read;
 
 use clap::Parser;
+use crossterm::{cursor::SetCursorStyle, execute};
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    // stdin has to be drained before the terminal is taken over
+    let mut editor = if args.reads_stdin() {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        Editor::from_stdin(text)
+    } else {
+        Editor::new(args.file_paths)
+    };
+    editor.read_only = args.readonly;
+    if let Some(path) = &args.keymap {
+        editor.keymap = Keymap::load(path)?;
+    }
     let mut terminal = ratatui::init();
-    let mut editor = Editor::new(args.file_path);
-    editor.run(&mut terminal)?;
+    // runs before the hook `ratatui::init` set up, which restores the rest of the terminal
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_cursor_style();
+        hook(info);
+    }));
+    if args.syntax.is_some() {
+        editor.settings.syntax = args.syntax;
+        editor.reload_syntax();
+    }
+    let result = editor.run(&mut terminal);
+    restore_cursor_style();
     ratatui::restore();
 
-    Ok(())
+    Ok(result?)
+}
+
+/// Puts back the cursor shape the terminal had before the editor changed it per mode
+fn restore_cursor_style() {
+    let _ = execute!(std::io::stdout(), SetCursorStyle::DefaultUserShape);
 }
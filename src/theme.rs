@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use anyhow::bail;
 use ratatui::style::Color;
 use serde::Deserialize;
 use serde_with::{DisplayFromStr, serde_as};
@@ -33,6 +34,69 @@ pub struct ColourTheme {
 
     #[serde_as(as = "DisplayFromStr")]
     pub comment: Colour,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub foreground: Option<Colour>,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub cursorline: Option<Colour>,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub search_highlight: Option<Colour>,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub line_number: Option<Colour>,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub colorcolumn: Option<Colour>,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub invisible: Option<Colour>,
+}
+
+impl Default for ColourTheme {
+    fn default() -> Self {
+        toml::from_str(include_str!("../theme/default.toml")).expect("default theme is valid")
+    }
+}
+
+impl ColourTheme {
+    /// Colour of plain text, the identifier colour if the theme has none
+    pub fn foreground(&self) -> Colour {
+        self.foreground.unwrap_or(self.ident)
+    }
+
+    /// Background of the cursor line and column, derived from `background` if the theme has none
+    pub fn cursorline(&self) -> Colour {
+        self.cursorline
+            .unwrap_or_else(|| self.background.shifted(0x18))
+    }
+
+    /// Background of search matches, the keyword colour if the theme has none
+    pub fn search_highlight(&self) -> Colour {
+        self.search_highlight.unwrap_or(self.keyword)
+    }
+
+    /// Background of the `colorcolumn` guide, the cursor line colour if the theme has none
+    pub fn colorcolumn(&self) -> Colour {
+        self.colorcolumn.unwrap_or_else(|| self.cursorline())
+    }
+
+    /// Colour of the `:set list` markers, the comment colour if the theme has none
+    pub fn invisible(&self) -> Colour {
+        self.invisible.unwrap_or(self.comment)
+    }
+
+    /// Colour of the numbers in the gutter, the comment colour if the theme has none
+    pub fn line_number(&self) -> Colour {
+        self.line_number.unwrap_or(self.comment)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -42,26 +106,159 @@ pub struct Colour {
     b: u8,
 }
 
+impl Colour {
+    /// Moves the colour `amount` towards the opposite end of the brightness scale,
+    /// dark colours get lighter and light colours get darker
+    pub fn shifted(self, amount: u8) -> Self {
+        let brightness = (u16::from(self.r) + u16::from(self.g) + u16::from(self.b)) / 3;
+        let shift = |c: u8| {
+            if brightness < 0x80 {
+                c.saturating_add(amount)
+            } else {
+                c.saturating_sub(amount)
+            }
+        };
+        Colour {
+            r: shift(self.r),
+            g: shift(self.g),
+            b: shift(self.b),
+        }
+    }
+}
+
 impl From<Colour> for Color {
     fn from(val: Colour) -> Self {
         Color::Rgb(val.r, val.g, val.b)
     }
 }
 
+/// RGB values of the ANSI colour names, as the xterm default palette draws them
+fn named_colour(name: &str) -> Option<(u8, u8, u8)> {
+    let name = name
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let rgb = match name.as_str() {
+        "black" => (0x00, 0x00, 0x00),
+        "red" => (0xcd, 0x00, 0x00),
+        "green" => (0x00, 0xcd, 0x00),
+        "yellow" => (0xcd, 0xcd, 0x00),
+        "blue" => (0x00, 0x00, 0xee),
+        "magenta" => (0xcd, 0x00, 0xcd),
+        "cyan" => (0x00, 0xcd, 0xcd),
+        "gray" | "grey" | "lightgray" | "lightgrey" => (0xe5, 0xe5, 0xe5),
+        "darkgray" | "darkgrey" | "brightblack" => (0x7f, 0x7f, 0x7f),
+        "brightred" | "lightred" => (0xff, 0x00, 0x00),
+        "brightgreen" | "lightgreen" => (0x00, 0xff, 0x00),
+        "brightyellow" | "lightyellow" => (0xff, 0xff, 0x00),
+        "brightblue" | "lightblue" => (0x5c, 0x5c, 0xff),
+        "brightmagenta" | "lightmagenta" => (0xff, 0x00, 0xff),
+        "brightcyan" | "lightcyan" => (0x00, 0xff, 0xff),
+        "white" | "brightwhite" => (0xff, 0xff, 0xff),
+        _ => return None,
+    };
+    Some(rgb)
+}
+
 impl FromStr for Colour {
     type Err = anyhow::Error;
 
+    /// Parses an ANSI colour name like `red` or `brightblue`, or `#rgb`, `#rrggbb` or
+    /// `#rrggbbaa` where the `#` is optional and alpha is ignored
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with('#') {
-            let r: u8 = u8::from_str_radix(&s[1..=2], 16)?;
-            let g: u8 = u8::from_str_radix(&s[3..=4], 16)?;
-            let b: u8 = u8::from_str_radix(&s[5..=6], 16)?;
-            Ok(Colour { r, g, b })
-        } else {
-            let r: u8 = u8::from_str_radix(&s[0..=1], 16)?;
-            let g: u8 = u8::from_str_radix(&s[2..=3], 16)?;
-            let b: u8 = u8::from_str_radix(&s[4..=5], 16)?;
-            Ok(Colour { r, g, b })
+        if let Some((r, g, b)) = named_colour(s) {
+            return Ok(Colour { r, g, b });
+        }
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("invalid colour `{s}`, expected a colour name or hex digits");
+        }
+        let channel = |idx: usize, width: usize| {
+            let value = u8::from_str_radix(&hex[idx * width..(idx + 1) * width], 16)?;
+            anyhow::Ok(if width == 1 { value * 0x11 } else { value })
+        };
+        let width = match hex.len() {
+            3 => 1,
+            6 | 8 => 2,
+            len => bail!("invalid colour `{s}`, expected 3, 6 or 8 hex digits but got {len}"),
+        };
+        Ok(Colour {
+            r: channel(0, width)?,
+            g: channel(1, width)?,
+            b: channel(2, width)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(colour: Colour) -> (u8, u8, u8) {
+        (colour.r, colour.g, colour.b)
+    }
+
+    #[test]
+    fn test_parse_hex_colours() {
+        assert_eq!(rgb("#12abEF".parse().unwrap()), (0x12, 0xab, 0xef));
+        assert_eq!(rgb("12abef".parse().unwrap()), (0x12, 0xab, 0xef));
+        assert_eq!(rgb("#fa0".parse().unwrap()), (0xff, 0xaa, 0x00));
+        assert_eq!(rgb("#12abef80".parse().unwrap()), (0x12, 0xab, 0xef));
+    }
+
+    #[test]
+    fn test_named_colours() {
+        assert_eq!(rgb("red".parse().unwrap()), (0xcd, 0x00, 0x00));
+        assert_eq!(rgb("Bright_Blue".parse().unwrap()), (0x5c, 0x5c, 0xff));
+        assert_eq!(rgb("grey".parse().unwrap()), rgb("gray".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_theme_mixing_hex_and_named_colours() {
+        let theme: ColourTheme = toml::from_str(
+            r##"
+            keyword = "magenta"
+            ident = "#ffffff"
+            lit = "green"
+            delim = "#aaa"
+            types = "brightyellow"
+            extra = "cyan"
+            background = "#1e1e2eff"
+            function = "blue"
+            comment = "darkgray"
+            cursorline = "black"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(rgb(theme.keyword), (0xcd, 0x00, 0xcd));
+        assert_eq!(rgb(theme.delim), (0xaa, 0xaa, 0xaa));
+        assert_eq!(rgb(theme.background), (0x1e, 0x1e, 0x2e));
+        assert_eq!(rgb(theme.cursorline()), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_foreground_falls_back_to_ident() {
+        let mut theme = ColourTheme::default();
+        assert_eq!(rgb(theme.foreground()), (0xd4, 0xd4, 0xd4));
+        theme.foreground = None;
+        assert_eq!(rgb(theme.foreground()), rgb(theme.ident));
+    }
+
+    #[test]
+    fn test_reject_invalid_colours() {
+        for input in [
+            "",
+            "#",
+            "#ff",
+            "#ffff",
+            "#fffffff",
+            "#fffffffff",
+            "#ggg",
+            "#ffé",
+            "purple",
+        ] {
+            assert!(input.parse::<Colour>().is_err(), "{input}");
         }
     }
 }